@@ -6,5 +6,28 @@ waiting_sc_process = Waiting StarCraft: Remastered Process...
 waiting_sc_module = Waiting StarCraft: Remastered Module...
 fail_to_read_memory = Fail to read memory...
 waiting_map_using_scbank = Looking for the SCBank map...
-request_save_file_name = Requesting Map data...
-check_latest = Checking latest version...
\ No newline at end of file
+request_save_slot = Waiting for the map to pick a save slot...
+check_latest = Checking latest version...
+unsupported_version = Unsupported StarCraft: Remastered version...
+saved = Saved!
+loaded = Loaded!
+no_save_file = No saved data yet.
+transfer_failed = Failed to save or load map data...
+transferring = Transferring map data...
+backups = Backups
+no_backups = No backups yet.
+update_downloading = Downloading the update...
+update_installing = Installing the update...
+update_up_to_date = SCBank is up to date.
+update_installed = Updated! Restart SCBank to use it.
+update_failed_network = Couldn't reach the update server...
+update_failed_no_build = No update for this system...
+update_failed_install = Couldn't install the update...
+update_failed = The update failed...
+update_failed_signature = The update failed its signature check and wasn't installed...
+rollback_offer = This update hasn't attached to StarCraft yet. Click here to go back to the previous version.
+rolled_back = Rolled back. The previous version runs from the next start.
+rollback_failed = Couldn't go back to the previous version.
+update_available = An update is available
+install_update = Install
+not_now = Not now
\ No newline at end of file
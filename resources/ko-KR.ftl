@@ -6,5 +6,28 @@ waiting_sc_process = 스타크래프트: 리마스터 프로세스를 찾는 중
 waiting_sc_module = 스타크래프트: 리마스터 모듈을 찾는 중입니다...
 fail_to_read_memory = 메모리를 읽는데 실패하였습니다...
 waiting_map_using_scbank = SCBank 사용맵을 찾는 중입니다...
-request_save_file_name = SCBank 맵 데이터 요청 중입니다...
-check_latest = 최신 버전을 확인합니다...
\ No newline at end of file
+request_save_slot = 맵이 저장 슬롯을 고르기를 기다리는 중입니다...
+check_latest = 최신 버전을 확인합니다...
+unsupported_version = 지원하지 않는 스타크래프트: 리마스터 버전입니다...
+saved = 저장했습니다!
+loaded = 불러왔습니다!
+no_save_file = 저장된 데이터가 없습니다.
+transfer_failed = 맵 데이터를 저장하거나 불러오지 못했습니다...
+transferring = 맵 데이터를 전송하는 중입니다...
+backups = 백업
+no_backups = 아직 백업이 없습니다.
+update_downloading = 업데이트를 내려받는 중입니다...
+update_installing = 업데이트를 설치하는 중입니다...
+update_up_to_date = 최신 버전입니다.
+update_installed = 업데이트했습니다! SCBank를 다시 실행하세요.
+update_failed_network = 업데이트 서버에 연결하지 못했습니다...
+update_failed_no_build = 이 시스템용 업데이트가 없습니다...
+update_failed_install = 업데이트를 설치하지 못했습니다...
+update_failed = 업데이트에 실패했습니다...
+update_failed_signature = 업데이트 서명이 올바르지 않아 설치하지 않았습니다...
+rollback_offer = 이 업데이트로 스타크래프트에 연결하지 못했습니다. 여기를 눌러 이전 버전으로 돌아갑니다.
+rolled_back = 이전 버전으로 되돌렸습니다. 다음 실행부터 적용됩니다.
+rollback_failed = 이전 버전으로 되돌리지 못했습니다.
+update_available = 업데이트가 있습니다
+install_update = 설치
+not_now = 나중에
\ No newline at end of file
@@ -6,5 +6,28 @@ waiting_sc_process = 等待启动星际争霸:重新启动过程…
 waiting_sc_module = 等待启动星际争霸:重新启动模块…
 fail_to_read_memory = 失败读凭记忆…
 waiting_map_using_scbank = 寻找SCBank的地图使用地图设定…
-request_save_file_name = 申请地图数据…
-check_latest = 检查最新版本…
\ No newline at end of file
+request_save_slot = 正在等待地图选择存档槽位…
+check_latest = 检查最新版本…
+unsupported_version = 不支持的星际争霸:重制版版本…
+saved = 已保存！
+loaded = 已读取！
+no_save_file = 尚无存档数据。
+transfer_failed = 保存或读取地图数据失败…
+transferring = 正在传输地图数据…
+backups = 备份
+no_backups = 还没有备份。
+update_downloading = 正在下载更新…
+update_installing = 正在安装更新…
+update_up_to_date = 已是最新版本。
+update_installed = 已更新！请重新启动SCBank。
+update_failed_network = 无法连接更新服务器…
+update_failed_no_build = 没有适用于此系统的更新…
+update_failed_install = 无法安装更新…
+update_failed = 更新失败…
+update_failed_signature = 更新签名无效，未安装…
+rollback_offer = 此更新尚未连接到星际争霸。点击此处返回上一个版本。
+rolled_back = 已回滚。下次启动时运行上一个版本。
+rollback_failed = 无法返回上一个版本。
+update_available = 有可用的更新
+install_update = 安装
+not_now = 以后再说
\ No newline at end of file
@@ -0,0 +1,353 @@
+//! Command line interface. Without arguments SCBank opens its window.
+
+use std::{
+    path::PathBuf,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use scbank::{
+    mem_lib::MemoryBackend,
+    save::archive::Conflict,
+    session::{SCState, Session},
+    update::rollback::{self, Startup},
+};
+
+mod archive;
+mod inspect;
+
+pub const USAGE: &str = "\
+Usage: SCBank [COMMAND]
+
+Commands:
+    (none)         Open the launcher window
+    headless       Serve the SCBank map without a window, logging to the terminal
+    inspect FILE   Verify a save file and print what it holds; add --json for JSON
+    export FILE    Pack saves into an archive to carry them to another computer
+    import FILE    Unpack an archive made by `export`
+    rollback       Go back to the version of SCBank the last update replaced
+    help           Print this message
+
+Options:
+    --headless     Same as `headless`
+    -h, --help     Same as `help`
+
+Export options:
+    --map ID       Only the saves of the map with this identifier
+    --slot NAME    Only the saves in this slot
+
+Import options, for saves whose slot is taken:
+    --keep-newer   Keep whichever was saved last (default)
+    --keep-both    Import into a new slot next to the existing save
+    --overwrite    Replace the existing save, keeping it as a backup";
+
+/// Time between two updates of the session, as the window runs at 60 frames per second.
+const FRAME: Duration = Duration::from_millis(16);
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Gui,
+    Headless,
+    Help,
+    Rollback,
+    Inspect {
+        path: PathBuf,
+        json: bool,
+    },
+    Export {
+        path: PathBuf,
+        map: Option<String>,
+        slot: Option<String>,
+    },
+    Import {
+        path: PathBuf,
+        conflict: Conflict,
+    },
+}
+
+pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let command = match args.next() {
+        None => return Ok(Command::Gui),
+        Some(arg) => match arg.as_str() {
+            "headless" | "--headless" => Command::Headless,
+            "help" | "-h" | "--help" => Command::Help,
+            "rollback" => Command::Rollback,
+            "inspect" => return parse_inspect(args),
+            "export" => return parse_export(args),
+            "import" => return parse_import(args),
+            _ => return Err(format!("Unknown command: {}", arg)),
+        },
+    };
+    match args.next() {
+        Some(arg) => Err(format!("Unexpected argument: {}", arg)),
+        None => Ok(command),
+    }
+}
+
+fn parse_inspect<I: Iterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut path = None;
+    let mut json = false;
+    for arg in args {
+        match arg.as_str() {
+            "--json" => json = true,
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+            _ if path.is_none() => path = Some(PathBuf::from(&arg)),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+    match path {
+        Some(path) => Ok(Command::Inspect { path, json }),
+        None => Err("inspect needs the path of a save file".into()),
+    }
+}
+
+fn parse_export<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut path = None;
+    let mut map = None;
+    let mut slot = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--map" => map = Some(value(&arg, &mut args)?),
+            "--slot" => slot = Some(value(&arg, &mut args)?),
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+            _ if path.is_none() => path = Some(PathBuf::from(&arg)),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+    match path {
+        Some(path) => Ok(Command::Export { path, map, slot }),
+        None => Err("export needs the path of the archive to write".into()),
+    }
+}
+
+fn parse_import<I: Iterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut path = None;
+    let mut conflict = Conflict::KeepNewer;
+    for arg in args {
+        match arg.as_str() {
+            "--keep-newer" => conflict = Conflict::KeepNewer,
+            "--keep-both" => conflict = Conflict::KeepBoth,
+            "--overwrite" => conflict = Conflict::Overwrite,
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+            _ if path.is_none() => path = Some(PathBuf::from(&arg)),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+    match path {
+        Some(path) => Ok(Command::Import { path, conflict }),
+        None => Err("import needs the path of an archive".into()),
+    }
+}
+
+/// The value following `option`.
+fn value<I: Iterator<Item = String>>(option: &str, args: &mut I) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("{} needs a value", option))
+}
+
+/// Runs a command other than `Gui` and returns the exit code. `startup` is what
+/// `rollback::on_startup` found.
+pub fn run(command: Command, startup: Startup) -> i32 {
+    attach_console();
+    match command {
+        Command::Gui => 0,
+        Command::Headless => headless(startup),
+        Command::Help => {
+            println!("{}", USAGE);
+            0
+        }
+        Command::Rollback => roll_back(),
+        Command::Inspect { path, json } => inspect::run(&path, json),
+        Command::Export { path, map, slot } => {
+            archive::export(&path, map.as_deref(), slot.as_deref())
+        }
+        Command::Import { path, conflict } => archive::import(&path, conflict),
+    }
+}
+
+/// Polls the session like the window does, logging every state change.
+fn headless(startup: Startup) -> i32 {
+    let mut session = match Session::open() {
+        Ok(session) => session,
+        Err(e) => {
+            eprintln!("Could not start SCBank: {}", e);
+            return 1;
+        }
+    };
+    log(&format!(
+        "SCBank {} running headless",
+        env!("CARGO_PKG_VERSION")
+    ));
+    let mut on_trial = match startup {
+        Startup::Normal => false,
+        Startup::Trial => true,
+        Startup::Suspect => {
+            log("This update never attached to StarCraft; `SCBank rollback` goes back");
+            true
+        }
+    };
+    let mut last = None;
+    loop {
+        session.update();
+        let state = *session.state();
+        if on_trial && state.is_attached() {
+            rollback::confirm();
+            on_trial = false;
+        }
+        if last != Some(state) {
+            if let Some(message) = describe(&session, last) {
+                log(&message);
+            }
+            last = Some(state);
+        }
+        thread::sleep(FRAME);
+    }
+}
+
+fn roll_back() -> i32 {
+    match rollback::roll_back() {
+        Ok(()) => {
+            println!("Rolled back; the previous version runs from the next start.");
+            0
+        }
+        Err(e) => {
+            eprintln!("Could not roll back: {}", e);
+            1
+        }
+    }
+}
+
+/// What to log when the session enters its current state coming from `last`, if anything.
+fn describe(session: &Session<impl MemoryBackend>, last: Option<SCState>) -> Option<String> {
+    let slot = session.last_slot().as_ref().map_or("", String::as_str);
+    let message = match session.state() {
+        SCState::FindingProcess => "Waiting for StarCraft: Remastered...".into(),
+        SCState::FindingModule => "Waiting for the StarCraft: Remastered module...".into(),
+        SCState::UnsupportedVersion => format!(
+            "Unsupported StarCraft: Remastered version {}",
+            session.game_version().as_ref().map_or("?", String::as_str)
+        ),
+        SCState::FindingSCBankMap => "Looking for the SCBank map...".into(),
+        SCState::RequestSlot => match last {
+            // Back from a request; the outcome was logged already.
+            Some(SCState::Transferring)
+            | Some(SCState::Saved)
+            | Some(SCState::Loaded)
+            | Some(SCState::NoSaveFile) => return None,
+            _ => match session.namespace() {
+                Some(namespace) => format!("Serving saves in {}", namespace.dir().display()),
+                None => "Serving saves".into(),
+            },
+        },
+        SCState::Transferring => "Transferring map data...".into(),
+        SCState::Saved => format!("Saved {}", slot),
+        SCState::Loaded => format!("Loaded {}", slot),
+        SCState::NoSaveFile => format!("No save named {} yet", slot),
+        SCState::TransferFailed => "Failed to save or load map data".into(),
+    };
+    Some(message)
+}
+
+fn log(message: &str) {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    println!("[{}] {}", scbank::format_time(secs), message);
+}
+
+/// Lets a command print to the terminal it was started from; the release build has no console
+/// of its own on Windows.
+#[cfg(windows)]
+pub fn attach_console() {
+    use winapi::um::wincon::{AttachConsole, ATTACH_PARENT_PROCESS};
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+pub fn attach_console() {}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{parse, Command};
+    use scbank::save::archive::Conflict;
+
+    fn parse_args(args: &[&str]) -> Result<Command, String> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn opens_the_window_without_arguments() {
+        assert_eq!(parse_args(&[]), Ok(Command::Gui));
+    }
+
+    #[test]
+    fn parses_the_headless_flag() {
+        assert_eq!(parse_args(&["headless"]), Ok(Command::Headless));
+        assert_eq!(parse_args(&["--headless"]), Ok(Command::Headless));
+        assert!(parse_args(&["--headless", "extra"]).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_commands() {
+        assert_eq!(
+            parse_args(&["serve"]),
+            Err("Unknown command: serve".to_string())
+        );
+        assert!(parse_args(&["inspect", "save.scb", "--yaml"]).is_err());
+    }
+
+    #[test]
+    fn needs_paths() {
+        assert!(parse_args(&["inspect"]).is_err());
+        assert!(parse_args(&["inspect", "--json"]).is_err());
+        assert!(parse_args(&["export", "--map", "map"]).is_err());
+        assert!(parse_args(&["import"]).is_err());
+        assert!(parse_args(&["import", "a.scba", "b.scba"]).is_err());
+        assert_eq!(
+            parse_args(&["inspect", "save.scb", "--json"]),
+            Ok(Command::Inspect {
+                path: PathBuf::from("save.scb"),
+                json: true,
+            })
+        );
+    }
+
+    #[test]
+    fn parses_export_filters() {
+        assert_eq!(
+            parse_args(&["export", "out.scba", "--map", "map", "--slot", "hero"]),
+            Ok(Command::Export {
+                path: PathBuf::from("out.scba"),
+                map: Some("map".into()),
+                slot: Some("hero".into()),
+            })
+        );
+        assert!(parse_args(&["export", "out.scba", "--map"]).is_err());
+    }
+
+    #[test]
+    fn parses_conflict_modes() {
+        let import = |args: &[&str]| match parse_args(args) {
+            Ok(Command::Import { conflict, .. }) => conflict,
+            other => panic!("Not an import: {:?}", other),
+        };
+        assert_eq!(import(&["import", "in.scba"]), Conflict::KeepNewer);
+        assert_eq!(
+            import(&["import", "in.scba", "--keep-newer"]),
+            Conflict::KeepNewer
+        );
+        assert_eq!(
+            import(&["import", "--keep-both", "in.scba"]),
+            Conflict::KeepBoth
+        );
+        assert_eq!(
+            import(&["import", "in.scba", "--overwrite"]),
+            Conflict::Overwrite
+        );
+        assert!(parse_args(&["import", "in.scba", "--keep-older"]).is_err());
+    }
+}
@@ -0,0 +1,188 @@
+//! `SCBank export` and `SCBank import`: moving saves between machines.
+
+use std::{
+    fs,
+    io::{self, BufRead},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use scbank::{
+    config::Config,
+    save::{
+        self,
+        archive::{self, Archive, Conflict, Imported},
+        Secret,
+    },
+};
+
+/// Packs the saves of this user into `path`, all of them or those of `map` and `slot`.
+pub fn export(path: &Path, map: Option<&str>, slot: Option<&str>) -> i32 {
+    // Saves can only have been made with an existing secret, so never create one here.
+    let secret = match Secret::load() {
+        Ok(Some(secret)) => secret,
+        Ok(None) => {
+            eprintln!("No save secret on this computer, so no saves to export.");
+            return 1;
+        }
+        Err(e) => {
+            eprintln!("Could not load the save secret: {}", e);
+            return 1;
+        }
+    };
+    let found = match save::root()
+        .map_err(Into::into)
+        .and_then(|root| archive::find_saves(&root, &secret))
+    {
+        Ok(found) => found,
+        Err(e) => {
+            eprintln!("Could not look for saves: {}", e);
+            return 1;
+        }
+    };
+
+    let mut saves = Vec::new();
+    for (file, save) in found {
+        match save {
+            Ok(save) => {
+                let wanted = map.is_none_or(|map| save.map() == map)
+                    && slot.is_none_or(|slot| save.slot() == slot);
+                if wanted {
+                    println!("Exporting {}", describe(save.map(), save.slot()));
+                    saves.push(save);
+                }
+            }
+            // Only mention files that could have matched.
+            Err(e) if map.is_none() && slot.is_none() => {
+                eprintln!("Skipping {}: {}", file.display(), e)
+            }
+            Err(_) => (),
+        }
+    }
+    if saves.is_empty() {
+        eprintln!("No saves to export.");
+        return 1;
+    }
+
+    let passphrase = match choose_passphrase() {
+        Ok(passphrase) => passphrase,
+        Err(e) => {
+            eprintln!("Could not read the passphrase: {}", e);
+            return 1;
+        }
+    };
+    let exported = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let count = saves.len();
+    let written = Archive::new(saves, exported)
+        .encode(&passphrase)
+        .and_then(|file| save::write_atomic(path, &file).map_err(Into::into));
+    match written {
+        Ok(()) => {
+            println!("Exported {} save(s) to {}", count, path.display());
+            0
+        }
+        Err(e) => {
+            eprintln!("Could not write {}: {}", path.display(), e);
+            1
+        }
+    }
+}
+
+/// Unpacks the saves in `path`, resolving clashes with existing saves as `conflict` says.
+pub fn import(path: &Path, conflict: Conflict) -> i32 {
+    let file = match fs::read(path) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("Could not read {}: {}", path.display(), e);
+            return 1;
+        }
+    };
+    let passphrase = match read_passphrase("Passphrase of the archive: ") {
+        Ok(passphrase) => passphrase,
+        Err(e) => {
+            eprintln!("Could not read the passphrase: {}", e);
+            return 1;
+        }
+    };
+    let archive = match Archive::decode(&file, &passphrase) {
+        Ok(archive) => archive,
+        Err(e) => {
+            eprintln!("Could not read {}: {}", path.display(), e);
+            return 1;
+        }
+    };
+    // Only now that the archive checks out: on a fresh computer this creates the secret the
+    // saves are signed with from here on.
+    let (root, secret) = match (save::root(), Secret::load_or_create()) {
+        (Ok(root), Ok(secret)) => (root, secret),
+        (Err(e), _) => {
+            eprintln!("Could not find the save folder: {}", e);
+            return 1;
+        }
+        (_, Err(e)) => {
+            eprintln!("Could not load the save secret: {}", e);
+            return 1;
+        }
+    };
+    println!(
+        "Archive of {} save(s) exported by SCBank {} on {}",
+        archive.saves().len(),
+        archive.manifest().scbank(),
+        scbank::format_time(*archive.manifest().exported())
+    );
+
+    let mut failed = false;
+    for (save, imported) in archive.import(&root, &secret, Config::load().backups, conflict) {
+        let name = describe(save.map(), save.slot());
+        match imported {
+            Ok(Imported::Added) => println!("Added {}", name),
+            Ok(Imported::Replaced) => println!("Replaced {}", name),
+            Ok(Imported::KeptExisting) => println!("Kept {}, it is newer", name),
+            Ok(Imported::Renamed(slot)) => println!("Imported {} as {}", name, slot),
+            Ok(Imported::KeptEncrypted) => println!(
+                "Kept {}, the imported save is encrypted and can't be renamed",
+                name
+            ),
+            Err(e) => {
+                eprintln!("Could not import {}: {}", name, e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        1
+    } else {
+        0
+    }
+}
+
+/// Asks for the passphrase of a new archive, twice to catch typos.
+fn choose_passphrase() -> io::Result<String> {
+    let passphrase = read_passphrase("Passphrase to import the archive with: ")?;
+    if read_passphrase("Repeat the passphrase: ")? != passphrase {
+        return Err(io::Error::other("the passphrases don't match"));
+    }
+    Ok(passphrase)
+}
+
+/// Reads a passphrase from the terminal after showing `prompt`. It is echoed as typed.
+fn read_passphrase(prompt: &str) -> io::Result<String> {
+    eprint!("{}", prompt);
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    let passphrase = line.trim_end_matches(['\r', '\n']);
+    if passphrase.is_empty() {
+        return Err(io::Error::other("the passphrase can't be empty"));
+    }
+    Ok(passphrase.into())
+}
+
+fn describe(map: &str, slot: &str) -> String {
+    if map.is_empty() {
+        slot.into()
+    } else {
+        format!("{} of {}", slot, map)
+    }
+}
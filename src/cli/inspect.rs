@@ -0,0 +1,290 @@
+//! `SCBank inspect`: what a save file holds, for map makers checking what their triggers
+//! stored.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use serde::{Serialize, Serializer};
+
+use scbank::{
+    mem_lib,
+    save::{Container, Secret, FORMAT_VERSION},
+};
+
+/// Bytes per line of the hex dump.
+const HEX_WIDTH: usize = 16;
+
+/// Whether the tag of the save was checked.
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Tag {
+    Valid,
+    /// This computer has no save secret to check it with, so the save can't be trusted.
+    NoSecret,
+}
+
+#[derive(Serialize)]
+struct Report {
+    file: String,
+    format_version: u16,
+    map: String,
+    slot: String,
+    created: u64,
+    modified: u64,
+    compressed: bool,
+    encrypted: bool,
+    tag: Tag,
+    /// Length of the payload as stored, after compression and encryption.
+    body_len: usize,
+    /// The decompressed payload; `None` if it is encrypted, as the key comes from the map.
+    #[serde(serialize_with = "hex")]
+    payload: Option<Vec<u8>>,
+    /// The payload as little endian dwords, the unit triggers read and write.
+    words: Option<Vec<u32>>,
+}
+
+pub fn run(path: &Path, json: bool) -> i32 {
+    let report = match Secret::load().and_then(|secret| inspect(path, secret.as_ref())) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Could not inspect {}: {}", path.display(), e);
+            return 1;
+        }
+    };
+    if json {
+        match serde_json::to_string_pretty(&report) {
+            Ok(s) => println!("{}", s),
+            Err(e) => {
+                eprintln!("Could not write JSON: {}", e);
+                return 1;
+            }
+        }
+    } else if let Err(e) = print(&report, &mut io::stdout().lock()) {
+        eprintln!("Could not print the report: {}", e);
+        return 1;
+    }
+    0
+}
+
+/// Reads the save at `path`, verifying it with the secret of this user if there is one.
+fn inspect(path: &Path, secret: Option<&Secret>) -> mem_lib::Result<Report> {
+    let file = fs::read(path)?;
+    let (container, tag) = match secret {
+        Some(secret) => {
+            let (map, slot) = Container::peek(&file)?;
+            let key = secret.key(&map, &slot);
+            (Container::decode(&file, &key, &slot)?, Tag::Valid)
+        }
+        None => (Container::decode_unverified(&file)?, Tag::NoSecret),
+    };
+    let payload = if container.is_encrypted() {
+        None
+    } else {
+        Some(container.open(None)?)
+    };
+    let words = payload.as_ref().map(|payload| {
+        payload
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect()
+    });
+    Ok(Report {
+        file: path.display().to_string(),
+        format_version: FORMAT_VERSION,
+        map: container.map().clone(),
+        slot: container.slot().clone(),
+        created: *container.created(),
+        modified: *container.modified(),
+        compressed: container.is_compressed(),
+        encrypted: container.is_encrypted(),
+        tag,
+        body_len: container.body().len(),
+        payload,
+        words,
+    })
+}
+
+fn print(report: &Report, out: &mut impl Write) -> io::Result<()> {
+    let mut flags = Vec::new();
+    if report.compressed {
+        flags.push("compressed");
+    }
+    if report.encrypted {
+        flags.push("encrypted");
+    }
+    writeln!(out, "File:      {}", report.file)?;
+    writeln!(out, "Format:    {}", report.format_version)?;
+    writeln!(
+        out,
+        "Map:       {}",
+        if report.map.is_empty() {
+            "(none)"
+        } else {
+            &report.map
+        }
+    )?;
+    writeln!(out, "Slot:      {}", report.slot)?;
+    writeln!(out, "Created:   {}", scbank::format_time(report.created))?;
+    writeln!(out, "Modified:  {}", scbank::format_time(report.modified))?;
+    writeln!(
+        out,
+        "Flags:     {}",
+        if flags.is_empty() {
+            "none".to_string()
+        } else {
+            flags.join(", ")
+        }
+    )?;
+    writeln!(
+        out,
+        "Tag:       {}",
+        match report.tag {
+            Tag::Valid => "valid",
+            Tag::NoSecret => "not checked, no save secret on this computer",
+        }
+    )?;
+    writeln!(out, "Stored:    {} bytes", report.body_len)?;
+    let payload = match &report.payload {
+        Some(payload) => payload,
+        None => {
+            return writeln!(out, "Payload:   encrypted, the map holds the key");
+        }
+    };
+    writeln!(out, "Payload:   {} bytes", payload.len())?;
+    if payload.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(out)?;
+    for (line, bytes) in payload.chunks(HEX_WIDTH).enumerate() {
+        let hex: Vec<_> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let text: String = bytes
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        writeln!(
+            out,
+            "{:08x}  {:<width$}  |{}|",
+            line * HEX_WIDTH,
+            hex.join(" "),
+            text,
+            width = HEX_WIDTH * 3 - 1
+        )?;
+    }
+
+    if let Some(words) = report.words.as_ref().filter(|words| !words.is_empty()) {
+        writeln!(out)?;
+        writeln!(out, "  index  offset  hex         decimal")?;
+        for (index, word) in words.iter().enumerate() {
+            writeln!(
+                out,
+                "{:>7}  {:>6}  0x{:08x}  {}",
+                index,
+                index * 4,
+                word,
+                *word as i32
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn hex<S: Serializer>(payload: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+    match payload {
+        Some(payload) => {
+            let hex: String = payload.iter().map(|byte| format!("{:02x}", byte)).collect();
+            serializer.serialize_some(&hex)
+        }
+        None => serializer.serialize_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use tempfile::TempDir;
+
+    use super::{inspect, print, Tag};
+    use scbank::save::{Container, Secret};
+
+    fn secret() -> Secret {
+        Secret::new([7; 32])
+    }
+
+    /// Writes a save of `payload` to the slot `hero` of `map`.
+    fn save(dir: &TempDir, payload: &[u8]) -> PathBuf {
+        let container = Container::seal("map", "hero", payload, None).unwrap();
+        let path = dir.path().join("hero.scb");
+        fs::write(&path, container.encode(&secret().key("map", "hero"))).unwrap();
+        path
+    }
+
+    fn printed(path: &Path, secret: Option<&Secret>) -> String {
+        let mut out = Vec::new();
+        print(&inspect(path, secret).unwrap(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn prints_the_payload() {
+        let dir = TempDir::new().unwrap();
+        let path = save(&dir, b"gold\x01\x00\x00\x00");
+        let printed = printed(&path, Some(&secret()));
+        for line in &[
+            "Map:       map",
+            "Slot:      hero",
+            "Flags:     none",
+            "Tag:       valid",
+            "Payload:   8 bytes",
+            "00000000  67 6f 6c 64 01 00 00 00",
+            "|gold....|",
+            "      0       0  0x646c6f67  1684828007",
+            "      1       4  0x00000001  1",
+        ] {
+            assert!(printed.contains(line), "{:?} not in\n{}", line, printed);
+        }
+    }
+
+    #[test]
+    fn reports_a_missing_secret() {
+        let dir = TempDir::new().unwrap();
+        let path = save(&dir, b"gold");
+        let report = inspect(&path, None).unwrap();
+        assert_eq!(report.tag, Tag::NoSecret);
+        assert_eq!(report.payload.as_ref().unwrap(), b"gold");
+        assert!(printed(&path, None)
+            .contains("Tag:       not checked, no save secret on this computer"));
+        // With a secret, the save of another user fails rather than being shown as valid.
+        assert!(inspect(&path, Some(&Secret::new([8; 32]))).is_err());
+    }
+
+    #[test]
+    fn writes_json() {
+        let dir = TempDir::new().unwrap();
+        let path = save(&dir, b"gold");
+        let json = serde_json::to_value(inspect(&path, Some(&secret())).unwrap()).unwrap();
+        assert_eq!(json["format_version"], 1);
+        assert_eq!(json["map"], "map");
+        assert_eq!(json["slot"], "hero");
+        assert_eq!(json["tag"], "valid");
+        assert_eq!(json["encrypted"], false);
+        assert_eq!(json["payload"], "676f6c64");
+        assert_eq!(json["words"], serde_json::json!([0x646c_6f67]));
+
+        let json = serde_json::to_value(inspect(&path, None).unwrap()).unwrap();
+        assert_eq!(json["tag"], "no_secret");
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{de::Error, Deserialize, Deserializer};
+use toml::{value::Table, Value};
+
+/// Launcher settings, looked up in the working directory.
+pub const CONFIG_FILE: &str = "scbank.toml";
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    /// Previous versions kept of every save; `0` disables backups.
+    pub backups: usize,
+    pub update: UpdateConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            backups: 5,
+            update: UpdateConfig::default(),
+        }
+    }
+}
+
+/// The `[update]` table.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UpdateConfig {
+    pub channel: Channel,
+    /// Where the update button looks for new releases.
+    pub source: UpdateSource,
+}
+
+impl<'de> Deserialize<'de> for UpdateConfig {
+    /// Reads the channel and the source from the same table, where `source` may be left out.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut table = Table::deserialize(deserializer)?;
+        let channel = match table.remove("channel") {
+            Some(channel) => channel.try_into().map_err(D::Error::custom)?,
+            None => Channel::default(),
+        };
+        if !table.contains_key("source") {
+            table.insert("source".into(), "github".into());
+        }
+        let source = Value::Table(table).try_into().map_err(D::Error::custom)?;
+        Ok(UpdateConfig { channel, source })
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    #[default]
+    Stable,
+    /// Pre-releases as well, versions such as `0.3.0-beta.1`.
+    Beta,
+}
+
+impl Channel {
+    pub fn includes(self, version: &str) -> bool {
+        match self {
+            Channel::Stable => !version.split('+').next().unwrap_or("").contains('-'),
+            Channel::Beta => true,
+        }
+    }
+}
+
+/// Kinds of update sources, told apart by the `source` key.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(tag = "source", rename_all = "lowercase")]
+pub enum UpdateSource {
+    /// The releases of SCBank on GitHub.
+    #[default]
+    GitHub,
+    /// A folder with a release manifest, see `update::source`.
+    Directory { path: PathBuf },
+    /// A static HTTP server with a release manifest at `url`.
+    Mirror { url: String },
+}
+
+impl Config {
+    pub fn parse(s: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(s)
+    }
+
+    /// Reads `CONFIG_FILE`, falling back to the defaults if it is missing or invalid.
+    pub fn load() -> Self {
+        let path = Path::new(CONFIG_FILE);
+        match fs::read_to_string(path) {
+            Ok(s) => Self::parse(&s).unwrap_or_else(|e| {
+                eprintln!("Ignoring {}: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{Channel, Config, UpdateConfig, UpdateSource};
+
+    #[test]
+    fn defaults_missing_settings() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        let config = Config::parse("[update]").unwrap();
+        assert_eq!(config.update, UpdateConfig::default());
+        assert_eq!(config.update.source, UpdateSource::GitHub);
+    }
+
+    #[test]
+    fn reads_the_update_table() {
+        let config = Config::parse(
+            r#"
+            backups = 2
+
+            [update]
+            channel = "beta"
+            source = "mirror"
+            url = "https://example.com/scbank/"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.backups, 2);
+        assert_eq!(
+            config.update,
+            UpdateConfig {
+                channel: Channel::Beta,
+                source: UpdateSource::Mirror {
+                    url: "https://example.com/scbank/".into()
+                },
+            }
+        );
+
+        let config =
+            Config::parse("[update]\nsource = \"directory\"\npath = \"releases\"").unwrap();
+        assert_eq!(config.update.channel, Channel::Stable);
+        assert_eq!(
+            config.update.source,
+            UpdateSource::Directory {
+                path: PathBuf::from("releases")
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_update_tables() {
+        for toml in &[
+            "[update]\nchannel = \"nightly\"",
+            "[update]\nsource = \"ftp\"",
+            "[update]\nsource = \"mirror\"",
+            "[update]\nchannel = 1",
+        ] {
+            assert!(Config::parse(toml).is_err(), "{}", toml);
+        }
+    }
+
+    #[test]
+    fn channels_include_their_releases() {
+        for version in &["0.2.0", "1.0.0+build-5"] {
+            assert!(Channel::Stable.includes(version), "{}", version);
+            assert!(Channel::Beta.includes(version), "{}", version);
+        }
+        for version in &["0.3.0-beta.1", "0.3.0-rc.1+build-5"] {
+            assert!(!Channel::Stable.includes(version), "{}", version);
+            assert!(Channel::Beta.includes(version), "{}", version);
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! The core of SCBank: finding StarCraft: Remastered, serving the requests of the SCBank map
+//! and keeping its saves. The launcher window and the command line are built on it.
+//!
+//! `session::Session` ties everything together; poll it with `Session::update` and read its
+//! state. `mem_lib::fake`, enabled by the `fake` feature, stands in for the game when testing
+//! a tool without it.
+
+// `failure`'s derive puts its impls inside a constant, which newer compilers warn about.
+#![allow(unknown_lints, non_local_definitions)]
+
+pub mod config;
+pub mod mem_lib;
+pub mod offsets;
+pub mod pe;
+pub mod protocol;
+pub mod save;
+pub mod session;
+pub mod transfer;
+pub mod update;
+
+/// Formats seconds since the Unix epoch as UTC, `YYYY-MM-DD HH:MM`.
+pub fn format_time(secs: u64) -> String {
+    // Howard Hinnant's civil_from_days.
+    let z = (secs / 86400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    let minutes = secs % 86400 / 60;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    )
+}
@@ -1,6 +1,6 @@
 #![windows_subsystem = "windows"]
 
-use std::{collections::hash_map::RandomState, collections::HashMap, env, f32, io, mem, path};
+use std::{collections::hash_map::RandomState, collections::HashMap, env, f32, io, path, process};
 
 use cgmath;
 use fluent_bundle::{FluentBundle, FluentResource};
@@ -12,22 +12,31 @@ use ggez::{
 };
 use webbrowser;
 
+use scbank::{
+    config::Config,
+    format_time,
+    mem_lib::MemoryBackend,
+    save,
+    session::{SCState, Session},
+    update::{
+        self,
+        rollback::{self, Startup},
+        Changes, UpdateErrorKind, UpdateState, Updater,
+    },
+};
+
 mod asset;
+mod cli;
 mod get_time;
-mod mem_lib;
 // mod scr;
 
-const STARCRAFT_VERSION: &str = "1.22.4.5993";
-const BUFFER_PTR: u32 = 0xBFD6E8;
-
-#[derive(PartialEq)]
-enum SCState {
-    FindingProcess,
-    FindingModule,
-    FindingSCBankMap,
-    RequestFilename,
-    CheckingLatestVersion,
-}
+/// Lines of the slot list under the status text; more don't fit the window.
+const MAX_LIST_LINES: usize = 4;
+const LIST_TOP: f32 = 150.0;
+const LIST_LINE_HEIGHT: f32 = 17.0;
+const PROGRESS_HEIGHT: f32 = 12.0;
+/// Where "not now" starts on the line offering an update.
+const DECLINE_LEFT: f32 = 240.0;
 
 enum TextColor {
     Green,
@@ -35,6 +44,15 @@ enum TextColor {
     Tan,
 }
 
+/// Going back to the version the last update replaced.
+#[derive(Clone, Copy, PartialEq)]
+enum Rollback {
+    /// The update never attached to StarCraft when it ran before.
+    Offered,
+    Done,
+    Failed,
+}
+
 struct MouseInfo {
     button: MouseButton,
     down: bool,
@@ -42,19 +60,28 @@ struct MouseInfo {
     y: f32,
 }
 
-struct MainState<'a> {
+struct MainState<'a, B: MemoryBackend> {
     font: Font,
     mouse_info: MouseInfo,
     locale: &'a str,
     fluent_bundles: HashMap<&'a str, FluentBundle<'a>, RandomState>,
     assets: asset::Assets,
-    state: SCState,
-    wait: u8,
-    process: mem_lib::GameProcess,
-    module: mem_lib::Module,
+    session: Session<B>,
+    /// Slot whose backups are listed instead of the slots.
+    selected_slot: Option<String>,
+    backups: Vec<save::Backup>,
+    /// First slot or backup shown in the list.
+    list_scroll: usize,
+    /// The update started with the update button, until its result has been shown.
+    updater: Option<Updater>,
+    /// First line of the release notes shown.
+    notes_scroll: usize,
+    /// The update installed last hasn't attached to StarCraft yet.
+    on_trial: bool,
+    rollback: Option<Rollback>,
 }
 
-impl<'a> MainState<'a> {
+impl<'a, B: MemoryBackend> MainState<'a, B> {
     pub fn get_text(&self, id: &str) -> String {
         let (value, _errors) = self
             .fluent_bundles
@@ -65,66 +92,211 @@ impl<'a> MainState<'a> {
         value
     }
 
-    pub fn update_app(&mut self) -> Result<(), Box<::std::error::Error>> {
-        use self_update::{self, cargo_crate_version};
-        let mut state = SCState::CheckingLatestVersion;
-        mem::swap(&mut self.state, &mut state);
-        let target = self_update::get_target()?;
-        let _releases = self_update::backends::github::ReleaseList::configure()
-            .repo_owner("armoha")
-            .repo_name("SCBank")
-            .with_target(&target)
-            .build()?
-            .fetch()?;
-
-        let _status = self_update::backends::github::Update::configure()?
-            .repo_owner("armoha")
-            .repo_name("SCBank")
-            .target(&target)
-            .bin_name("SCBank.exe")
-            .show_download_progress(true)
-            .current_version(cargo_crate_version!())
-            .build()?
-            .update()?;
-        mem::swap(&mut self.state, &mut state);
-        Ok(())
+    /// Starts an update unless one is running.
+    fn start_update(&mut self) {
+        match self.updater {
+            Some(ref updater) if !updater.state().is_finished() => (),
+            _ => {
+                let config = Config::load().update;
+                let source = update::source::from_config(&config.source);
+                self.updater = Some(Updater::start(source, config.channel));
+                self.notes_scroll = 0;
+            }
+        }
     }
 
-    pub fn get_sc_proc(&mut self) -> SCState {
-        self.process = match mem_lib::get_proc_by_name("StarCraft.exe") {
-            Ok(proc) => proc,
-            Err(_) => {
-                self.wait = 127;
-                return SCState::FindingProcess;
+    fn roll_back(&mut self) {
+        self.rollback = match rollback::roll_back() {
+            Ok(()) => Some(Rollback::Done),
+            Err(e) => {
+                eprintln!("Couldn't roll back: {}", e);
+                Some(Rollback::Failed)
             }
         };
-        SCState::FindingModule
+        self.on_trial = false;
     }
 
-    pub fn get_sc_module(&mut self) -> SCState {
-        self.module = match self.process.get_module("StarCraft.exe") {
-            Ok(module) => module,
-            Err(_) => {
-                return SCState::FindingProcess;
+    fn is_listing_slots(&self) -> bool {
+        if self.updater.is_some() {
+            return false;
+        }
+        matches!(
+            self.session.state(),
+            SCState::RequestSlot | SCState::Saved | SCState::Loaded | SCState::NoSaveFile
+        )
+    }
+
+    /// The slot list, or the backups of the selected slot behind a back line.
+    fn list_lines(&self) -> Vec<String> {
+        match self.selected_slot {
+            Some(ref slot) => {
+                let mut lines = vec![format!("< {}: {}", self.get_text("backups"), slot)];
+                if self.backups.is_empty() {
+                    lines.push(self.get_text("no_backups"));
+                }
+                lines.extend(
+                    self.backups
+                        .iter()
+                        .skip(self.list_scroll)
+                        .take(MAX_LIST_LINES - 1)
+                        .map(|backup| format_time(*backup.taken() / 1000)),
+                );
+                lines
             }
-        };
-        SCState::FindingSCBankMap
+            None => self
+                .session
+                .slots()
+                .iter()
+                .skip(self.list_scroll)
+                .take(MAX_LIST_LINES)
+                .map(|slot| {
+                    format!(
+                        "{}  {}  {:.1} KB",
+                        slot.slot(),
+                        format_time(*slot.played()),
+                        *slot.size() as f32 / 1024.0
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// The index of the slot or backup drawn on `line` of the list; `None` for the back line.
+    fn list_entry(&self, line: usize) -> Option<usize> {
+        match self.selected_slot {
+            Some(_) if line == 0 => None,
+            Some(_) => Some(self.list_scroll + line - 1),
+            None => Some(self.list_scroll + line),
+        }
+    }
+
+    /// How many slots or backups there are to list.
+    fn list_len(&self) -> usize {
+        match self.selected_slot {
+            Some(_) => self.backups.len(),
+            None => self.session.slots().len(),
+        }
     }
 
-    pub fn check_scbank_map(&mut self) -> SCState {
-        self.module
-            .write::<u32>(&self.process, 0xBEFB88, 0)
-            .unwrap();
-        match self.module.read::<u32>(BUFFER_PTR + 212, &self.process) {
-            Ok(value) => {
-                if value == 0x5537F23B {
-                    return SCState::RequestFilename;
+    /// Selects a slot to list its backups, or restores the backup clicked on. Any other line of
+    /// the backups goes back to the slots.
+    fn click_list(&mut self, line: usize) {
+        // Below the list, or an entry that is there but scrolled out of view.
+        if line >= self.list_lines().len() {
+            return;
+        }
+        let entry = self.list_entry(line);
+        match self.selected_slot.take() {
+            Some(_) => {
+                if let Some(backup) = entry.and_then(|entry| self.backups.get(entry)) {
+                    if let Err(e) = self.session.restore(backup) {
+                        eprintln!("Couldn't restore the backup: {}", e);
+                    }
                 }
-                SCState::FindingSCBankMap
+                self.backups.clear();
+            }
+            None => {
+                let slot = match entry.and_then(|entry| self.session.slots().get(entry)) {
+                    Some(slot) => slot.slot().clone(),
+                    None => return,
+                };
+                self.backups = self.session.backups_of(&slot);
+                self.selected_slot = Some(slot);
             }
-            Err(_) => SCState::FindingProcess,
         }
+        self.list_scroll = 0;
     }
+
+    /// The notes of every release since this version, each under its version.
+    fn notes_lines(&self, changes: &[Changes]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for change in changes {
+            lines.push(format!("v{}", change.version));
+            lines.extend(
+                change
+                    .notes
+                    .get(self.locale)
+                    .trim()
+                    .lines()
+                    .map(|line| line.trim_end().to_string()),
+            );
+        }
+        lines
+    }
+
+    /// Draws the newest release offered with what changed since this version, scrolled to
+    /// `notes_scroll`. Lines too long for the window wrap, and what doesn't fit is cut off.
+    fn draw_release_notes(&self, ctx: &mut Context, changes: &[Changes]) -> GameResult {
+        let green = Color::new(0.03, 0.9, 0.03, 1.0);
+        let tan = Color::new(0.953, 0.851, 0.796, 1.0);
+        let install = format!(
+            "{} v{}",
+            self.get_text("install_update"),
+            changes[0].version
+        );
+        let mut text = Text::new((install, self.font, 14.0));
+        let txtdst = cgmath::Point2::new(24.0, LIST_TOP);
+        text.set_bounds(
+            cgmath::Point2::new(DECLINE_LEFT - 24.0, f32::INFINITY),
+            Align::Left,
+        );
+        graphics::draw(ctx, &text, (txtdst, green))?;
+
+        let mut text = Text::new((self.get_text("not_now"), self.font, 14.0));
+        let txtdst = cgmath::Point2::new(DECLINE_LEFT, LIST_TOP);
+        text.set_bounds(
+            cgmath::Point2::new(456.0 - DECLINE_LEFT, f32::INFINITY),
+            Align::Right,
+        );
+        graphics::draw(ctx, &text, (txtdst, tan))?;
+
+        let notes = self.notes_lines(changes);
+        let shown = notes.get(self.notes_scroll..).unwrap_or(&[]).join("\n");
+        let mut text = Text::new((shown, self.font, 14.0));
+        let txtdst = cgmath::Point2::new(24.0, LIST_TOP + LIST_LINE_HEIGHT);
+        text.set_bounds(cgmath::Point2::new(432.0, f32::INFINITY), Align::Left);
+        graphics::draw(ctx, &text, (txtdst, tan))
+    }
+
+    /// Draws how much of the update is downloaded where the slots are listed.
+    fn draw_update_progress(&self, ctx: &mut Context, state: &UpdateState) -> GameResult {
+        let progress = match state.progress() {
+            Some(progress) => progress,
+            None => match state {
+                UpdateState::Downloading { .. } => 0.0,
+                _ => return Ok(()),
+            },
+        };
+        let tan = Color::new(0.953, 0.851, 0.796, 1.0);
+        let green = Color::new(0.03, 0.9, 0.03, 1.0);
+        let origin = cgmath::Point2::new(0.0, 0.0);
+        let bounds = graphics::Rect::new(24.0, LIST_TOP, 432.0, PROGRESS_HEIGHT);
+        let outline =
+            graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::stroke(1.0), bounds, tan)?;
+        graphics::draw(ctx, &outline, (origin,))?;
+        if progress > 0.0 {
+            let filled = graphics::Rect::new(24.0, LIST_TOP, 432.0 * progress, PROGRESS_HEIGHT);
+            let bar =
+                graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), filled, green)?;
+            graphics::draw(ctx, &bar, (origin,))?;
+        }
+
+        if let UpdateState::Downloading { done, total } = *state {
+            let size = match total {
+                Some(total) => format!("{} / {}", megabytes(done), megabytes(total)),
+                None => megabytes(done),
+            };
+            let mut text = Text::new((size, self.font, 14.0));
+            let txtdst = cgmath::Point2::new(24.0, LIST_TOP + PROGRESS_HEIGHT + 4.0);
+            text.set_bounds(cgmath::Point2::new(432.0, f32::INFINITY), Align::Center);
+            graphics::draw(ctx, &text, (txtdst, tan))?;
+        }
+        Ok(())
+    }
+}
+
+fn megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1_048_576.0)
 }
 
 trait InRange {
@@ -137,20 +309,22 @@ impl InRange for f32 {
     }
 }
 
-impl<'a> event::EventHandler for MainState<'a> {
+impl<'a, B: MemoryBackend> event::EventHandler for MainState<'a, B> {
     fn update(&mut self, _ctx: &mut Context) -> GameResult {
-        if self.wait > 0 {
-            self.wait -= 1;
-            return Ok(());
+        self.session.update();
+        if self.on_trial && self.session.state().is_attached() {
+            rollback::confirm();
+            self.on_trial = false;
+            if self.rollback == Some(Rollback::Offered) {
+                self.rollback = None;
+            }
+        }
+        if let Some(ref mut updater) = self.updater {
+            updater.poll();
+            if updater.is_done() {
+                self.updater = None;
+            }
         }
-        self.state = match self.state {
-            SCState::FindingProcess => self.get_sc_proc(),
-            SCState::FindingModule => self.get_sc_module(),
-            SCState::FindingSCBankMap => self.check_scbank_map(),
-            SCState::RequestFilename => return Ok(()),
-            _ => SCState::FindingProcess,
-        };
-
         Ok(())
     }
 
@@ -210,20 +384,54 @@ impl<'a> event::EventHandler for MainState<'a> {
             }
         } else {
             let tan = Color::new(0.953, 0.851, 0.796, 1.0);
-            let text = format!("v{}\n{}", env!("CARGO_PKG_VERSION"), STARCRAFT_VERSION);
+            let starcraft_version = match self.session.game_version() {
+                Some(version) => version.as_str(),
+                None => "",
+            };
+            let text = format!("v{}\n{}", env!("CARGO_PKG_VERSION"), starcraft_version);
             let mut text = Text::new((text, self.font, 11.0));
             let txtdst = cgmath::Point2::new(332.0, 20.0);
             text.set_bounds(cgmath::Point2::new(70.0, f32::INFINITY), Align::Center);
             graphics::draw(ctx, &text, (txtdst, tan))?;
         }
 
-        let text = match self.state {
-            SCState::FindingProcess => Some(("waiting_sc_process", TextColor::LightBlue)),
-            SCState::FindingModule => Some(("waiting_sc_module", TextColor::LightBlue)),
-            SCState::FindingSCBankMap => Some(("waiting_map_using_scbank", TextColor::LightBlue)),
-            SCState::RequestFilename => Some(("request_save_file_name", TextColor::LightBlue)),
-            SCState::CheckingLatestVersion => Some(("check_latest", TextColor::Tan)),
-            _ => None,
+        let text = match self.updater.as_ref().map(Updater::state) {
+            Some(UpdateState::Checking) => Some(("check_latest", TextColor::Tan)),
+            Some(UpdateState::Available(_)) => Some(("update_available", TextColor::Green)),
+            Some(UpdateState::Downloading { .. }) => {
+                Some(("update_downloading", TextColor::LightBlue))
+            }
+            Some(UpdateState::Installing) => Some(("update_installing", TextColor::LightBlue)),
+            Some(UpdateState::UpToDate) => Some(("update_up_to_date", TextColor::Green)),
+            Some(UpdateState::Updated(_)) => Some(("update_installed", TextColor::Green)),
+            Some(UpdateState::Failed(e)) => Some((
+                match e {
+                    UpdateErrorKind::Network(_) => "update_failed_network",
+                    UpdateErrorKind::NoBuild(_) => "update_failed_no_build",
+                    UpdateErrorKind::Install(_) => "update_failed_install",
+                    UpdateErrorKind::Unsigned | UpdateErrorKind::BadSignature => {
+                        "update_failed_signature"
+                    }
+                    UpdateErrorKind::BadManifest(_)
+                    | UpdateErrorKind::NoPublicKey
+                    | UpdateErrorKind::Interrupted => "update_failed",
+                },
+                TextColor::Tan,
+            )),
+            None => match self.session.state() {
+                SCState::FindingProcess => Some(("waiting_sc_process", TextColor::LightBlue)),
+                SCState::FindingModule => Some(("waiting_sc_module", TextColor::LightBlue)),
+                SCState::UnsupportedVersion => Some(("unsupported_version", TextColor::Tan)),
+                SCState::FindingSCBankMap => {
+                    Some(("waiting_map_using_scbank", TextColor::LightBlue))
+                }
+                SCState::RequestSlot => Some(("request_save_slot", TextColor::LightBlue)),
+                SCState::Transferring => Some(("transferring", TextColor::LightBlue)),
+                SCState::Saved => Some(("saved", TextColor::Green)),
+                SCState::Loaded => Some(("loaded", TextColor::Green)),
+                SCState::NoSaveFile => Some(("no_save_file", TextColor::Tan)),
+                SCState::TransferFailed => Some(("transfer_failed", TextColor::Tan)),
+            },
         };
         match text {
             Some((text, color)) => {
@@ -241,6 +449,34 @@ impl<'a> event::EventHandler for MainState<'a> {
             None => (),
         }
 
+        if let Some(ref updater) = self.updater {
+            match updater.state() {
+                UpdateState::Available(changes) => self.draw_release_notes(ctx, changes)?,
+                state => self.draw_update_progress(ctx, state)?,
+            }
+        }
+
+        if self.is_listing_slots() {
+            let tan = Color::new(0.953, 0.851, 0.796, 1.0);
+            for (i, line) in self.list_lines().into_iter().enumerate() {
+                let mut text = Text::new((line, self.font, 14.0));
+                let txtdst = cgmath::Point2::new(24.0, LIST_TOP + i as f32 * LIST_LINE_HEIGHT);
+                text.set_bounds(cgmath::Point2::new(432.0, f32::INFINITY), Align::Left);
+                graphics::draw(ctx, &text, (txtdst, tan))?;
+            }
+        } else if let (Some(rollback), None) = (self.rollback, &self.updater) {
+            let text = match rollback {
+                Rollback::Offered => "rollback_offer",
+                Rollback::Done => "rolled_back",
+                Rollback::Failed => "rollback_failed",
+            };
+            let tan = Color::new(0.953, 0.851, 0.796, 1.0);
+            let mut text = Text::new((self.get_text(text), self.font, 14.0));
+            let txtdst = cgmath::Point2::new(24.0, LIST_TOP);
+            text.set_bounds(cgmath::Point2::new(432.0, f32::INFINITY), Align::Left);
+            graphics::draw(ctx, &text, (txtdst, tan))?;
+        }
+
         graphics::present(ctx)?;
         Ok(())
     }
@@ -249,12 +485,30 @@ impl<'a> event::EventHandler for MainState<'a> {
         let mouse = &mut self.mouse_info;
         mouse.button = button;
         mouse.down = true;
-        if y >= 22.0 && y <= 42.0 && x >= 128.0 && x <= 211.0 {
+        if button == MouseButton::Left && y >= LIST_TOP && x >= 24.0 && x <= 456.0 {
+            let offered = matches!(
+                self.updater.as_ref().map(Updater::state),
+                Some(UpdateState::Available(_))
+            );
+            if offered {
+                if y < LIST_TOP + LIST_LINE_HEIGHT {
+                    self.assets.mousedown_sound.play_detached().unwrap();
+                    match self.updater {
+                        Some(ref updater) if x < DECLINE_LEFT => updater.install(),
+                        _ => self.updater = None,
+                    }
+                }
+            } else if self.is_listing_slots() {
+                self.click_list(((y - LIST_TOP) / LIST_LINE_HEIGHT) as usize);
+            } else if self.updater.is_none() && self.rollback == Some(Rollback::Offered) {
+                self.assets.mousedown_sound.play_detached().unwrap();
+                self.roll_back();
+            }
+        } else if y >= 22.0 && y <= 42.0 && x >= 128.0 && x <= 211.0 {
             match x {
                 x if x < 148.0 => {
-                    // TODO: Implement AutoUpdate
                     self.assets.mousedown_sound.play_detached().unwrap();
-                    self.update_app().unwrap();
+                    self.start_update();
                 }
                 x if x.in_range(149.0, 169.0) => {
                     self.assets.mousedown_sound.play_detached().unwrap();
@@ -275,6 +529,13 @@ impl<'a> event::EventHandler for MainState<'a> {
                     fn open_browser(path: &path::Path) -> io::Result<bool> {
                         use std::process::{Command, Stdio};
 
+                        fn find_cmd<'a>(commands: &[&'a str]) -> Option<&'a str> {
+                            let paths = env::var_os("PATH")?;
+                            commands.iter().cloned().find(|cmd| {
+                                env::split_paths(&paths).any(|dir| dir.join(cmd).is_file())
+                            })
+                        }
+
                         let env_browser = env::var_os("BROWSER")
                             .map(|b| env::split_paths(&b).collect::<Vec<_>>());
                         let env_commands: Vec<&str> = env_browser
@@ -350,6 +611,27 @@ impl<'a> event::EventHandler for MainState<'a> {
         mouse.down = false;
     }
 
+    fn mouse_wheel_event(&mut self, _ctx: &mut Context, _x: f32, y: f32) {
+        if let Some(UpdateState::Available(changes)) = self.updater.as_ref().map(Updater::state) {
+            let lines = self.notes_lines(changes).len();
+            if y > 0.0 {
+                self.notes_scroll = self.notes_scroll.saturating_sub(1);
+            } else if y < 0.0 && self.notes_scroll + 1 < lines {
+                self.notes_scroll += 1;
+            }
+        } else if self.is_listing_slots() {
+            let rows = match self.selected_slot {
+                Some(_) => MAX_LIST_LINES - 1,
+                None => MAX_LIST_LINES,
+            };
+            if y > 0.0 {
+                self.list_scroll = self.list_scroll.saturating_sub(1);
+            } else if y < 0.0 && self.list_scroll + rows < self.list_len() {
+                self.list_scroll += 1;
+            }
+        }
+    }
+
     fn mouse_motion_event(&mut self, ctx: &mut Context, x: f32, y: f32, xrel: f32, yrel: f32) {
         let mouse = &mut self.mouse_info;
         if mouse.down
@@ -369,15 +651,31 @@ impl<'a> event::EventHandler for MainState<'a> {
 }
 
 pub fn main() -> GameResult {
+    // First of all, so an update that fails to start at all still counts as started.
+    let startup = rollback::on_startup();
+    match cli::parse(env::args().skip(1)) {
+        Ok(cli::Command::Gui) => (),
+        Ok(command) => process::exit(cli::run(command, startup)),
+        Err(e) => {
+            cli::attach_console();
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            process::exit(2);
+        }
+    }
+
+    // Before the window, so it doesn't flash up when SCBank can't start.
+    let session = Session::open().map_err(|e| {
+        let message = format!("Could not open the save folder: {}", e);
+        #[cfg(windows)]
+        windows::show_error(&message);
+        GameError::ResourceLoadError(message)
+    })?;
+
     let resource_dir = path::PathBuf::from("./resources");
     let cb = ContextBuilder::new("SCBank", "Armoha")
         .window_setup(
             conf::WindowSetup::default()
-                .title(&format!(
-                    "SCBank {} (StarCraft: Remastered {})",
-                    env!("CARGO_PKG_VERSION"),
-                    STARCRAFT_VERSION
-                ))
+                .title(&format!("SCBank {}", env!("CARGO_PKG_VERSION")))
                 .transparent(true),
         )
         .window_mode(
@@ -432,8 +730,10 @@ pub fn main() -> GameResult {
         .unwrap();
     let font = Font::new_glyph_font_bytes(ctx, &font).unwrap_or_default();
     let assets = asset::Assets::new(ctx)?;
-    let proc = mem_lib::GameProcess::current_process();
-    let module = proc.get_module("SCBank.exe").unwrap();
+    let rollback = match startup {
+        Startup::Suspect if rollback::can_roll_back() => Some(Rollback::Offered),
+        _ => None,
+    };
 
     // println!("{}", get_time::get_utc_tm());
 
@@ -448,10 +748,14 @@ pub fn main() -> GameResult {
         locale: "ko-KR",
         fluent_bundles: fluent_bundles,
         assets: assets,
-        state: SCState::FindingProcess,
-        wait: 0,
-        process: proc,
-        module: module,
+        session,
+        selected_slot: None,
+        backups: Vec::new(),
+        list_scroll: 0,
+        updater: None,
+        notes_scroll: 0,
+        on_trial: startup != Startup::Normal,
+        rollback,
     };
     event::run(ctx, event_loop, state)
 }
@@ -476,4 +780,21 @@ pub mod windows {
         }
         inner(s.as_ref())
     }
+
+    /// Shows `message` in a dialog; the release build has no console to print it to.
+    pub fn show_error(message: &str) {
+        use std::ptr;
+        use winapi::um::winuser::{MessageBoxW, MB_ICONERROR, MB_OK};
+
+        if let (Ok(text), Ok(caption)) = (to_u16s(message), to_u16s("SCBank")) {
+            unsafe {
+                MessageBoxW(
+                    ptr::null_mut(),
+                    text.as_ptr(),
+                    caption.as_ptr(),
+                    MB_OK | MB_ICONERROR,
+                );
+            }
+        }
+    }
 }
@@ -1,19 +1,24 @@
-use std::{ffi::OsString, mem, os::windows::ffi::OsStringExt};
-// source: https://www.unknowncheats.me/forum/general-programming-and-reversing/330583-pure-rust-injectable-dll.html
 use std::result::Result as StdResult;
+use std::{convert::TryFrom, mem, ptr, slice};
 
 use failure::{Error as FError, Fail};
 use getset::Getters;
-use winapi::um::{handleapi, memoryapi, processthreadsapi, tlhelp32, winnt};
 
-#[derive(Getters)]
-#[get = "pub"]
-pub struct GameProcess {
-    handle: winnt::HANDLE,
-    pid: u32,
-}
-
-#[derive(Getters, Default)]
+#[cfg(any(test, feature = "fake"))]
+pub mod fake;
+#[cfg(target_os = "linux")]
+mod linux;
+mod scan;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(target_os = "linux")]
+pub use self::linux::{get_proc_by_name, GameProcess};
+pub use self::scan::Pattern;
+#[cfg(windows)]
+pub use self::windows::{get_proc_by_name, GameProcess};
+
+#[derive(Getters, Default, Clone, Copy, Debug, PartialEq)]
 #[get = "pub"]
 pub struct Module {
     base: u32,
@@ -38,158 +43,123 @@ pub enum ProcessErrorKind {
 
     #[fail(display = "InvalidBytesWritten: {}", _0)]
     InvalidBytesWritten(u32),
+
+    #[fail(display = "Couldn't open process {}", _0)]
+    OpenProcess(u32),
+
+    #[fail(display = "Invalid byte pattern: {}", _0)]
+    InvalidPattern(String),
+
+    #[fail(display = "Pattern not found: {}", _0)]
+    PatternNotFound(String),
+
+    #[fail(display = "Offset {:#X} is outside the module", _0)]
+    OutOfModule(u32),
 }
 
-impl GameProcess {
-    pub fn current_process() -> Self {
-        Self::new(unsafe { processthreadsapi::GetCurrentProcess() })
+/// Raw access to the memory of a (StarCraft) process.
+///
+/// `GameProcess` talks to a live process through winapi on Windows and through procfs on
+/// Linux (StarCraft under Wine/Proton); other implementations let the launcher run without
+/// one.
+pub trait MemoryBackend {
+    /// Attaches to the first process whose executable name contains `name`.
+    fn find_process(&mut self, name: &str) -> Result<()>;
+
+    /// Lists the name and address range of every module loaded in the attached process.
+    fn modules(&self) -> Result<Vec<(String, Module)>>;
+
+    /// Fills `buf` with the bytes at `address`.
+    fn read_bytes(&self, address: u32, buf: &mut [u8]) -> Result<()>;
+
+    /// Copies `buf` to `address`.
+    fn write_bytes(&self, address: u32, buf: &[u8]) -> Result<()>;
+
+    fn get_module(&self, module_name: &str) -> Result<Module> {
+        self.modules()?
+            .into_iter()
+            .find(|(name, _)| name.contains(module_name))
+            .map(|(_, module)| module)
+            .ok_or_else(|| ProcessErrorKind::UnknownModule(module_name.into()).into())
     }
+}
 
-    pub fn new(handle: winnt::HANDLE) -> Self {
-        let pid = unsafe { processthreadsapi::GetProcessId(handle) };
-        GameProcess { handle, pid }
+impl Module {
+    pub fn new(base: u32, size: u32) -> Self {
+        Module { base, size }
     }
 
-    /*pub fn read_memory(&self, address: u32) -> Result<u32> {
-        let mut read = unsafe { mem::uninitialized() };
-        let mut amount_read: libc::size_t = 0;
-
-        if unsafe {
-            memoryapi::ReadProcessMemory(
-                self.handle,
-                address as *const _,
-                &mut read as *mut _ as *mut _,
-                mem::size_of::<u32>() as _,
-                &mut amount_read as *mut _,
-            )
-        } != (true as _)
-            || amount_read == 0
-        {
-            mem::forget(read);
-            return Err(ProcessErrorKind::MemoryRead(address).into());
+    /// The address of `len` bytes at `offset`, which must all lie inside the module.
+    pub fn address(&self, offset: u32, len: usize) -> Result<u32> {
+        let end = u32::try_from(len)
+            .ok()
+            .and_then(|len| offset.checked_add(len));
+        match end {
+            Some(end) if end <= self.size => self
+                .base
+                .checked_add(offset)
+                .ok_or_else(|| ProcessErrorKind::OutOfModule(offset).into()),
+            _ => Err(ProcessErrorKind::OutOfModule(offset).into()),
         }
+    }
 
-        Ok(read)
-    }*/
-
-    pub fn get_module(&self, module_name: &str) -> Result<Module> {
-        let module =
-            unsafe { tlhelp32::CreateToolhelp32Snapshot(tlhelp32::TH32CS_SNAPMODULE, self.pid) };
-        if module == handleapi::INVALID_HANDLE_VALUE {
-            return Err(ProcessErrorKind::InvalidHandleValue.into());
-        }
+    pub fn read<T: Copy>(&self, offset: u32, parent: &impl MemoryBackend) -> Result<T> {
+        let mut buf = vec![0u8; mem::size_of::<T>()];
+        parent.read_bytes(self.address(offset, buf.len())?, &mut buf)?;
+        Ok(unsafe { ptr::read_unaligned(buf.as_ptr() as *const T) })
+    }
 
-        let mut entry: tlhelp32::MODULEENTRY32W = unsafe { mem::zeroed() };
-        entry.dwSize = mem::size_of::<tlhelp32::MODULEENTRY32W>() as _;
-
-        while unsafe { tlhelp32::Module32NextW(module, &mut entry) } != 0 {
-            let name = OsString::from_wide(&entry.szModule[..]).into_string();
-            let name = match name {
-                Err(e) => {
-                    eprintln!("Couldn't convert into String: {:?}", e);
-                    continue;
-                }
-                Ok(s) => s,
-            };
-
-            if name.contains(module_name) {
-                unsafe { handleapi::CloseHandle(module) };
-
-                /*println!(
-                    "Base address of {}: 0x{:X} @ size of 0x{:X}",
-                    module_name, entry.modBaseAddr as u32, entry.modBaseSize
-                );*/
-
-                return Ok(Module {
-                    base: entry.modBaseAddr as _,
-                    size: entry.modBaseSize as _,
-                });
-            }
-        }
+    /// Fills `buf` with the bytes at `offset`.
+    pub fn read_into(
+        &self,
+        offset: u32,
+        buf: &mut [u8],
+        parent: &impl MemoryBackend,
+    ) -> Result<()> {
+        parent.read_bytes(self.address(offset, buf.len())?, buf)
+    }
 
-        Err(ProcessErrorKind::UnknownModule(module_name.into()).into())
+    /// Copies `buf` to `offset`.
+    pub fn write_from(&self, offset: u32, buf: &[u8], parent: &impl MemoryBackend) -> Result<()> {
+        parent.write_bytes(self.address(offset, buf.len())?, buf)
     }
-}
 
-pub fn get_proc_by_name(name: &str) -> Result<GameProcess> {
-    let mut process: tlhelp32::PROCESSENTRY32W = unsafe { mem::uninitialized() };
-    process.dwSize = mem::size_of::<tlhelp32::PROCESSENTRY32W>() as u32;
-
-    //Make a Snapshot of all the current process.
-    let snapshot = unsafe { tlhelp32::CreateToolhelp32Snapshot(tlhelp32::TH32CS_SNAPPROCESS, 0) };
-
-    //Get the first process and store it in process variable.
-    if unsafe { tlhelp32::Process32FirstW(snapshot, &mut process) } != 0 {
-        //Take the next process if possible.
-        while unsafe { tlhelp32::Process32NextW(snapshot, &mut process) } != 0 {
-            let process_name = OsString::from_wide(&process.szExeFile);
-
-            match process_name.into_string() {
-                Ok(s) => {
-                    if s.contains(name) {
-                        return Ok(GameProcess {
-                            handle: unsafe {
-                                processthreadsapi::OpenProcess(
-                                    winnt::PROCESS_VM_READ
-                                        | winnt::PROCESS_VM_OPERATION
-                                        | winnt::PROCESS_VM_WRITE,
-                                    0,
-                                    process.th32ProcessID,
-                                )
-                            },
-                            pid: process.th32ProcessID,
-                        });
-                    }
-                }
-                Err(_) => {
-                    println!(
-                        "Error converting process name for PID {}",
-                        process.th32ProcessID
-                    );
-                }
-            }
-        }
+    pub fn write<T: Copy>(
+        &mut self,
+        parent: &impl MemoryBackend,
+        offset: u32,
+        value: T,
+    ) -> Result<()> {
+        let buf =
+            unsafe { slice::from_raw_parts(&value as *const T as *const u8, mem::size_of::<T>()) };
+        parent.write_bytes(self.address(offset, buf.len())?, buf)
     }
-    Err(ProcessErrorKind::UnknownProcess(name.into()).into())
 }
 
-impl Module {
-    pub fn read<T>(&self, offset: u32, parent: &GameProcess) -> Result<T> {
-        let mut read = unsafe { mem::uninitialized() };
-        let mut amount_read: libc::size_t = 0;
-
-        if unsafe {
-            memoryapi::ReadProcessMemory(
-                *(&*parent).handle(),
-                (self.base + offset) as *const _,
-                &mut read as *mut _ as *mut _,
-                mem::size_of::<T>() as _,
-                &mut amount_read as *mut _,
-            )
-        } != (true as i32)
-            || amount_read == 0
-        {
-            mem::forget(read);
-            return Err(ProcessErrorKind::MemoryRead(self.base + offset).into());
-        }
-
-        Ok(read)
+#[cfg(test)]
+mod tests {
+    use super::{fake::FakeProcess, MemoryBackend, Module};
+
+    #[test]
+    fn offsets_stay_inside_the_module() {
+        let mut process = FakeProcess::new("StarCraft.exe", 0x40_0000, 0x100);
+        process.find_process("StarCraft.exe").unwrap();
+        let mut module = process.module();
+        module.write(&process, 0xFC, 0x1234_5678u32).unwrap();
+        assert_eq!(module.read::<u32>(0xFC, &process).unwrap(), 0x1234_5678);
+
+        assert!(module.read::<u32>(0xFD, &process).is_err());
+        assert!(module.read::<u32>(0xFFFF_FFF0, &process).is_err());
+        assert!(module
+            .read_into(0xFFFF_FFF0, &mut [0; 0x20], &process)
+            .is_err());
+        assert!(module.write(&process, u32::MAX, 0u8).is_err());
     }
 
-    pub fn write<T>(&mut self, parent: &GameProcess, offset: u32, mut value: T) -> Result<()> {
-        if unsafe {
-            memoryapi::WriteProcessMemory(
-                *(&*parent).handle(),
-                (self.base + offset) as *mut _,
-                &mut value as *mut _ as *mut _,
-                mem::size_of_val(&value),
-                std::ptr::null_mut(),
-            ) as usize
-        } == 0
-        {
-            return Err(ProcessErrorKind::InvalidBytesWritten(self.base + offset).into());
-        }
-
-        Ok(())
+    #[test]
+    fn addresses_do_not_wrap() {
+        let module = Module::new(0xFFFF_FF00, 0x200);
+        assert_eq!(module.address(0x10, 4).unwrap(), 0xFFFF_FF10);
+        assert!(module.address(0x100, 4).is_err());
     }
 }
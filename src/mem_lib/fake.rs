@@ -0,0 +1,234 @@
+use std::{cell::RefCell, ops::Range, rc::Rc};
+
+use super::{MemoryBackend, Module, ProcessErrorKind, Result};
+use crate::{
+    offsets::Offsets,
+    protocol::{self, Header},
+};
+
+/// An in-memory stand-in for a running StarCraft process.
+///
+/// Clones share the same memory image, so a test can keep one handle to poke at memory or
+/// kill the process while the `Session` owns another.
+#[derive(Clone)]
+pub struct FakeProcess {
+    image: Rc<RefCell<Image>>,
+    attached: bool,
+}
+
+struct Image {
+    name: String,
+    running: bool,
+    module: Module,
+    memory: Vec<u8>,
+    /// Offsets that fail to read, like guard pages.
+    protected: Vec<Range<usize>>,
+}
+
+impl FakeProcess {
+    /// Creates a running process with a single zero-filled module named `name`.
+    pub fn new(name: &str, base: u32, size: u32) -> Self {
+        FakeProcess {
+            image: Rc::new(RefCell::new(Image {
+                name: name.into(),
+                running: true,
+                module: Module::new(base, size),
+                memory: vec![0; size as usize],
+                protected: Vec::new(),
+            })),
+            attached: false,
+        }
+    }
+
+    /// Creates a running process whose only module is a memory dump captured from a real
+    /// process, e.g. to check that a signature still matches a new build.
+    pub fn from_dump(name: &str, base: u32, dump: Vec<u8>) -> Self {
+        let process = Self::new(name, base, 0);
+        {
+            let mut image = process.image.borrow_mut();
+            image.module = Module::new(base, dump.len() as u32);
+            image.memory = dump;
+        }
+        process
+    }
+
+    /// Creates `StarCraft.exe` of the build described by `offsets` with an SCBank map loaded:
+    /// the flag word is set and an idle protocol header is present in the shared buffer.
+    pub fn scbank_map(offsets: &Offsets) -> Self {
+        let process = Self::new("StarCraft.exe", 0x40_0000, 0xC0_0000);
+        process.set_file_version(&offsets.version);
+        process.poke(offsets.flag_ptr, 1);
+        process.poke_bytes(
+            offsets.buffer_ptr + protocol::HEADER_OFFSET,
+            &Header::default().encode(),
+        );
+        process
+    }
+
+    /// Writes minimal PE headers with a `VS_VERSIONINFO` resource reporting `version`
+    /// (`major.minor.patch.build`) at the start of the module.
+    pub fn set_file_version(&self, version: &str) {
+        const NT_HEADER: u32 = 0x80;
+        const RESOURCES: u32 = 0x1000;
+
+        let mut parts = version
+            .split('.')
+            .map(|part| part.parse::<u32>().unwrap_or(0));
+        let mut part = || parts.next().unwrap_or(0) & 0xFFFF;
+        let ms = part() << 16 | part();
+        let ls = part() << 16 | part();
+
+        self.poke_bytes(0, b"MZ");
+        self.poke(0x3C, NT_HEADER);
+        self.poke_bytes(NT_HEADER, b"PE\0\0");
+        let optional_header = NT_HEADER + 24;
+        self.poke_bytes(optional_header, &0x10Bu16.to_le_bytes());
+        // Resource data directory.
+        self.poke(optional_header + 96 + 2 * 8, RESOURCES);
+        self.poke(optional_header + 96 + 2 * 8 + 4, 0x100);
+
+        // RT_VERSION -> id 1 -> language 0x409 -> data entry.
+        for &(directory, id, offset_to_data) in &[
+            (0x00, 16, 0x8000_0018),
+            (0x18, 1, 0x8000_0030),
+            (0x30, 0x409, 0x48),
+        ] {
+            self.poke_bytes(RESOURCES + directory + 14, &1u16.to_le_bytes());
+            self.poke(RESOURCES + directory + 16, id);
+            self.poke(RESOURCES + directory + 20, offset_to_data);
+        }
+        let info = RESOURCES + 0x60;
+        self.poke(RESOURCES + 0x48, info);
+        self.poke(RESOURCES + 0x4C, 92);
+
+        self.poke_bytes(info, &[92, 0, 52, 0, 0, 0]);
+        let key: Vec<u8> = "VS_VERSION_INFO\0"
+            .encode_utf16()
+            .flat_map(|c| c.to_le_bytes().to_vec())
+            .collect();
+        self.poke_bytes(info + 6, &key);
+        self.poke(info + 40, 0xFEEF_04BD);
+        self.poke(info + 44, 0x1_0000);
+        self.poke(info + 48, ms);
+        self.poke(info + 52, ls);
+    }
+
+    /// Reads the `u32` at `offset` from the module base.
+    pub fn peek(&self, offset: u32) -> u32 {
+        let mut buf = [0; 4];
+        self.image.borrow().copy_to(offset, &mut buf);
+        u32::from_le_bytes(buf)
+    }
+
+    /// Fills `buf` with the bytes at `offset` from the module base.
+    pub fn peek_bytes(&self, offset: u32, buf: &mut [u8]) {
+        self.image.borrow().copy_to(offset, buf);
+    }
+
+    /// Writes `value` at `offset` from the module base.
+    pub fn poke(&self, offset: u32, value: u32) {
+        self.poke_bytes(offset, &value.to_le_bytes());
+    }
+
+    pub fn poke_bytes(&self, offset: u32, bytes: &[u8]) {
+        self.image.borrow_mut().copy_from(offset, bytes);
+    }
+
+    /// Makes `len` bytes at `offset` fail to read, like a guard page.
+    pub fn protect(&self, offset: u32, len: u32) {
+        let start = offset as usize;
+        self.image
+            .borrow_mut()
+            .protected
+            .push(start..start + len as usize);
+    }
+
+    pub fn module(&self) -> Module {
+        self.image.borrow().module
+    }
+
+    /// Makes the process disappear: attached handles stop working and it can't be found.
+    pub fn kill(&self) {
+        self.image.borrow_mut().running = false;
+    }
+
+    /// Restarts a killed process with its memory intact.
+    pub fn restart(&self) {
+        self.image.borrow_mut().running = true;
+    }
+
+    fn check_attached(&self) -> Result<()> {
+        let image = self.image.borrow();
+        if !self.attached || !image.running {
+            return Err(ProcessErrorKind::UnknownProcess(image.name.clone()).into());
+        }
+        Ok(())
+    }
+}
+
+impl Image {
+    fn range(&self, address: u32, len: usize) -> Option<std::ops::Range<usize>> {
+        let start = address.checked_sub(*self.module.base())? as usize;
+        let end = start.checked_add(len)?;
+        if end > self.memory.len() {
+            return None;
+        }
+        Some(start..end)
+    }
+
+    fn copy_to(&self, offset: u32, buf: &mut [u8]) {
+        let start = offset as usize;
+        buf.copy_from_slice(&self.memory[start..start + buf.len()]);
+    }
+
+    fn copy_from(&mut self, offset: u32, bytes: &[u8]) {
+        let start = offset as usize;
+        self.memory[start..start + bytes.len()].copy_from_slice(bytes);
+    }
+}
+
+impl MemoryBackend for FakeProcess {
+    fn find_process(&mut self, name: &str) -> Result<()> {
+        let image = self.image.borrow();
+        if !image.running || !image.name.contains(name) {
+            return Err(ProcessErrorKind::UnknownProcess(name.into()).into());
+        }
+        drop(image);
+        self.attached = true;
+        Ok(())
+    }
+
+    fn modules(&self) -> Result<Vec<(String, Module)>> {
+        self.check_attached()?;
+        let image = self.image.borrow();
+        Ok(vec![(image.name.clone(), image.module)])
+    }
+
+    fn read_bytes(&self, address: u32, buf: &mut [u8]) -> Result<()> {
+        self.check_attached()?;
+        let image = self.image.borrow();
+        match image.range(address, buf.len()) {
+            Some(range)
+                if !image.protected.iter().any(|protected| {
+                    protected.start < range.end && range.start < protected.end
+                }) =>
+            {
+                buf.copy_from_slice(&image.memory[range]);
+                Ok(())
+            }
+            _ => Err(ProcessErrorKind::MemoryRead(address).into()),
+        }
+    }
+
+    fn write_bytes(&self, address: u32, buf: &[u8]) -> Result<()> {
+        self.check_attached()?;
+        let mut image = self.image.borrow_mut();
+        match image.range(address, buf.len()) {
+            Some(range) => {
+                image.memory[range].copy_from_slice(buf);
+                Ok(())
+            }
+            None => Err(ProcessErrorKind::InvalidBytesWritten(address).into()),
+        }
+    }
+}
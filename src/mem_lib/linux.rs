@@ -0,0 +1,148 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    os::unix::fs::FileExt,
+    path::Path,
+    process,
+};
+
+use getset::Getters;
+
+use super::{MemoryBackend, Module, ProcessErrorKind, Result};
+
+/// A process (usually StarCraft running under Wine/Proton) accessed through procfs.
+///
+/// Memory is read and written through `/proc/<pid>/mem`, which needs ptrace access to the
+/// target: run SCBank as the same user and with `kernel.yama.ptrace_scope` at 0, or give it
+/// `CAP_SYS_PTRACE`.
+#[derive(Getters)]
+pub struct GameProcess {
+    #[get = "pub"]
+    pid: u32,
+    mem: Option<File>,
+}
+
+impl GameProcess {
+    pub fn current_process() -> Self {
+        GameProcess {
+            pid: process::id(),
+            mem: None,
+        }
+    }
+
+    fn mem(&self) -> Result<&File> {
+        match self.mem {
+            Some(ref mem) => Ok(mem),
+            None => Err(ProcessErrorKind::OpenProcess(self.pid).into()),
+        }
+    }
+}
+
+impl MemoryBackend for GameProcess {
+    fn find_process(&mut self, name: &str) -> Result<()> {
+        *self = get_proc_by_name(name)?;
+        Ok(())
+    }
+
+    fn modules(&self) -> Result<Vec<(String, Module)>> {
+        let maps = fs::read_to_string(format!("/proc/{}/maps", self.pid))
+            .map_err(|_| ProcessErrorKind::UnknownProcess(self.pid.to_string()))?;
+
+        // Wine maps every PE image as several regions backed by the same file.
+        let mut order = Vec::new();
+        let mut ranges: HashMap<&str, (u64, u64)> = HashMap::new();
+        for line in maps.lines() {
+            // start-end perms offset dev inode pathname
+            let mut fields = line.splitn(6, ' ');
+            let range = fields.next().unwrap_or_default();
+            let path = fields.nth(4).unwrap_or_default().trim_start();
+            if !path.starts_with('/') {
+                continue;
+            }
+            let mut bounds = range.splitn(2, '-');
+            let start = bounds.next().and_then(|s| u64::from_str_radix(s, 16).ok());
+            let end = bounds.next().and_then(|s| u64::from_str_radix(s, 16).ok());
+            let (start, end) = match (start, end) {
+                (Some(start), Some(end)) => (start, end),
+                _ => continue,
+            };
+            let entry = ranges.entry(path).or_insert_with(|| {
+                order.push(path);
+                (start, end)
+            });
+            entry.0 = entry.0.min(start);
+            entry.1 = entry.1.max(end);
+        }
+
+        Ok(order
+            .into_iter()
+            .filter_map(|path| {
+                let (start, end) = ranges[path];
+                // SCBank addresses are 32-bit, so 64-bit mappings can't be the game.
+                if end > u64::from(u32::MAX) {
+                    return None;
+                }
+                let name = Path::new(path).file_name()?.to_string_lossy().into_owned();
+                Some((name, Module::new(start as u32, (end - start) as u32)))
+            })
+            .collect())
+    }
+
+    fn read_bytes(&self, address: u32, buf: &mut [u8]) -> Result<()> {
+        self.mem()?
+            .read_exact_at(buf, u64::from(address))
+            .map_err(|_| ProcessErrorKind::MemoryRead(address).into())
+    }
+
+    fn write_bytes(&self, address: u32, buf: &[u8]) -> Result<()> {
+        self.mem()?
+            .write_all_at(buf, u64::from(address))
+            .map_err(|_| ProcessErrorKind::InvalidBytesWritten(address).into())
+    }
+}
+
+/// Finds a process whose `comm` or `argv[0]` contains `name` and whose memory can be opened.
+///
+/// Wine keeps the Windows executable name in both, e.g. `C:\StarCraft\x86\StarCraft.exe`.
+/// If no match can be opened, the error is that of the first one.
+pub fn get_proc_by_name(name: &str) -> Result<GameProcess> {
+    let entries =
+        fs::read_dir("/proc").map_err(|_| ProcessErrorKind::UnknownProcess(name.into()))?;
+    let mut denied = None;
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let pid = match entry
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<u32>().ok())
+        {
+            Some(pid) if pid != process::id() => pid,
+            _ => continue,
+        };
+        let comm = fs::read_to_string(entry.path().join("comm")).unwrap_or_default();
+        let cmdline = fs::read(entry.path().join("cmdline")).unwrap_or_default();
+        let argv0 = cmdline.split(|&b| b == 0).next().unwrap_or_default();
+        if !comm.trim_end().contains(name) && !String::from_utf8_lossy(argv0).contains(name) {
+            continue;
+        }
+
+        let mem = match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(entry.path().join("mem"))
+        {
+            Ok(mem) => mem,
+            // Another match, such as the Wine preloader, may be accessible.
+            Err(_) => {
+                denied.get_or_insert(ProcessErrorKind::OpenProcess(pid));
+                continue;
+            }
+        };
+        return Ok(GameProcess {
+            pid,
+            mem: Some(mem),
+        });
+    }
+    Err(denied
+        .unwrap_or_else(|| ProcessErrorKind::UnknownProcess(name.into()))
+        .into())
+}
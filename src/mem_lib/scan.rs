@@ -0,0 +1,178 @@
+use std::result::Result as StdResult;
+use std::{cmp, str::FromStr};
+
+use super::{MemoryBackend, Module, ProcessErrorKind, Result};
+
+/// Bytes read from the target per call while scanning.
+const SCAN_CHUNK: usize = 0x10000;
+/// Granularity of memory protection, read one at a time when a chunk can't be read whole.
+const PAGE_SIZE: usize = 0x1000;
+
+/// A byte signature such as `"8B 0D ?? ?? ?? ?? 85 C9"`, where `??` matches any byte.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern {
+    bytes: Vec<Option<u8>>,
+}
+
+impl FromStr for Pattern {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = s
+            .split_whitespace()
+            .map(|token| match token {
+                "?" | "??" => Ok(None),
+                _ => u8::from_str_radix(token, 16).map(Some),
+            })
+            .collect::<StdResult<Vec<_>, _>>()
+            .map_err(|_| ProcessErrorKind::InvalidPattern(s.into()))?;
+        if bytes.is_empty() {
+            return Err(ProcessErrorKind::InvalidPattern(s.into()).into());
+        }
+        Ok(Pattern { bytes })
+    }
+}
+
+impl Pattern {
+    fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Returns the index of the first match in `haystack`.
+    pub fn find_in(&self, haystack: &[u8]) -> Option<usize> {
+        haystack.windows(self.bytes.len()).position(|window| {
+            window
+                .iter()
+                .zip(&self.bytes)
+                .all(|(byte, expected)| expected.is_none_or(|expected| *byte == expected))
+        })
+    }
+}
+
+impl Module {
+    /// Searches `[base, base + size)` for `pattern` and returns the offset of the first match.
+    ///
+    /// Memory is read in `SCAN_CHUNK` sized blocks. Blocks that can't be read whole are read
+    /// page by page, skipping the pages that can't be read (e.g. guard pages).
+    pub fn find_pattern(&self, pattern: &Pattern, parent: &impl MemoryBackend) -> Option<u32> {
+        let size = *self.size() as usize;
+        let overlap = pattern.len() - 1;
+        let mut buf = vec![0; SCAN_CHUNK + overlap];
+        let mut start = 0;
+        while start < size {
+            let len = cmp::min(SCAN_CHUNK + overlap, size - start);
+            let chunk = &mut buf[..len];
+            let found = if self.read_into(start as u32, chunk, parent).is_ok() {
+                pattern.find_in(chunk)
+            } else {
+                self.find_in_pages(pattern, start, chunk, parent)
+            };
+            if let Some(i) = found {
+                return Some((start + i) as u32);
+            }
+            start += SCAN_CHUNK;
+        }
+        None
+    }
+
+    /// Reads `chunk` from `start` a page at a time and searches each run of readable pages.
+    fn find_in_pages(
+        &self,
+        pattern: &Pattern,
+        start: usize,
+        chunk: &mut [u8],
+        parent: &impl MemoryBackend,
+    ) -> Option<usize> {
+        let mut run = 0;
+        let mut at = 0;
+        while at < chunk.len() {
+            let end = cmp::min(at + PAGE_SIZE, chunk.len());
+            if self
+                .read_into((start + at) as u32, &mut chunk[at..end], parent)
+                .is_err()
+            {
+                if let Some(i) = pattern.find_in(&chunk[run..at]) {
+                    return Some(run + i);
+                }
+                run = end;
+            }
+            at = end;
+        }
+        pattern.find_in(&chunk[run..]).map(|i| run + i)
+    }
+
+    /// Reads the absolute address stored at `offset` and returns it relative to the base.
+    pub fn resolve_absolute(&self, offset: u32, parent: &impl MemoryBackend) -> Result<u32> {
+        let address = self.read::<u32>(offset, parent)?;
+        address
+            .checked_sub(*self.base())
+            .ok_or_else(|| ProcessErrorKind::MemoryRead(address).into())
+    }
+
+    /// Resolves the `rel32` operand at `offset` (relative to the end of the operand) and
+    /// returns the target relative to the base.
+    pub fn resolve_relative(&self, offset: u32, parent: &impl MemoryBackend) -> Result<u32> {
+        let displacement = self.read::<i32>(offset, parent)?;
+        let next = offset
+            .checked_add(4)
+            .ok_or(ProcessErrorKind::OutOfModule(offset))?;
+        Ok(next.wrapping_add(displacement as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pattern, PAGE_SIZE, SCAN_CHUNK};
+    use crate::mem_lib::{fake::FakeProcess, MemoryBackend};
+
+    #[test]
+    fn parses_patterns() {
+        let pattern: Pattern = "8B 0D ?? ? 85".parse().unwrap();
+        assert_eq!(pattern.find_in(&[0, 0x8B, 0x0D, 1, 2, 0x85]), Some(1));
+        assert_eq!(pattern.find_in(&[0x8B, 0x0D, 1, 2, 0x84]), None);
+        assert!("".parse::<Pattern>().is_err());
+        assert!("8B GG".parse::<Pattern>().is_err());
+    }
+
+    #[test]
+    fn finds_patterns_across_chunks() {
+        let mut process = FakeProcess::new("StarCraft.exe", 0x40_0000, 0x3_0000);
+        process.find_process("StarCraft.exe").unwrap();
+        let at = SCAN_CHUNK as u32 - 2;
+        process.poke_bytes(at, &[0xDE, 0xAD, 0xBE, 0xEF]);
+        let pattern = "DE AD ?? EF".parse().unwrap();
+        assert_eq!(process.module().find_pattern(&pattern, &process), Some(at));
+        let missing = "DE AD BE EE".parse().unwrap();
+        assert_eq!(process.module().find_pattern(&missing, &process), None);
+    }
+
+    #[test]
+    fn finds_patterns_next_to_unreadable_pages() {
+        let mut process = FakeProcess::new("StarCraft.exe", 0x40_0000, 0x3_0000);
+        process.find_process("StarCraft.exe").unwrap();
+        let pattern = "DE AD ?? EF".parse().unwrap();
+        // In the first chunk, which can't be read whole.
+        process.protect(0x1000, PAGE_SIZE as u32);
+        let at = 0x2FFE;
+        process.poke_bytes(at, &[0xDE, 0xAD, 0xBE, 0xEF]);
+        assert_eq!(process.module().find_pattern(&pattern, &process), Some(at));
+
+        // A match cut by an unreadable page isn't there to find.
+        process.protect(0x3000, PAGE_SIZE as u32);
+        assert_eq!(process.module().find_pattern(&pattern, &process), None);
+    }
+
+    #[test]
+    fn resolves_operands() {
+        let mut process = FakeProcess::new("StarCraft.exe", 0x40_0000, 0x1000);
+        process.find_process("StarCraft.exe").unwrap();
+        let module = process.module();
+        process.poke(0x10, 0x40_0800);
+        assert_eq!(module.resolve_absolute(0x10, &process).unwrap(), 0x800);
+        process.poke(0x20, -0x14i32 as u32);
+        assert_eq!(module.resolve_relative(0x20, &process).unwrap(), 0x10);
+        process.poke(0x30, 0x10);
+        assert!(module.resolve_absolute(0x30, &process).is_err());
+        assert!(module.resolve_relative(0xFFFF_FFFE, &process).is_err());
+    }
+}
@@ -0,0 +1,166 @@
+use std::{ffi::OsString, mem, os::windows::ffi::OsStringExt, ptr};
+// source: https://www.unknowncheats.me/forum/general-programming-and-reversing/330583-pure-rust-injectable-dll.html
+
+use getset::Getters;
+use winapi::um::{handleapi, memoryapi, processthreadsapi, tlhelp32, winnt};
+
+use super::{MemoryBackend, Module, ProcessErrorKind, Result};
+
+#[derive(Getters)]
+#[get = "pub"]
+pub struct GameProcess {
+    handle: winnt::HANDLE,
+    pid: u32,
+}
+
+impl GameProcess {
+    pub fn current_process() -> Self {
+        Self::new(unsafe { processthreadsapi::GetCurrentProcess() })
+    }
+
+    pub fn new(handle: winnt::HANDLE) -> Self {
+        let pid = unsafe { processthreadsapi::GetProcessId(handle) };
+        GameProcess { handle, pid }
+    }
+}
+
+impl Drop for GameProcess {
+    fn drop(&mut self) {
+        // Closing the pseudo handle of the current process does nothing.
+        if !self.handle.is_null() {
+            unsafe { handleapi::CloseHandle(self.handle) };
+        }
+    }
+}
+
+impl MemoryBackend for GameProcess {
+    fn find_process(&mut self, name: &str) -> Result<()> {
+        *self = get_proc_by_name(name)?;
+        Ok(())
+    }
+
+    fn modules(&self) -> Result<Vec<(String, Module)>> {
+        let snapshot =
+            unsafe { tlhelp32::CreateToolhelp32Snapshot(tlhelp32::TH32CS_SNAPMODULE, self.pid) };
+        if snapshot == handleapi::INVALID_HANDLE_VALUE {
+            return Err(ProcessErrorKind::InvalidHandleValue.into());
+        }
+
+        let mut entry: tlhelp32::MODULEENTRY32W = unsafe { mem::zeroed() };
+        entry.dwSize = mem::size_of::<tlhelp32::MODULEENTRY32W>() as _;
+
+        let mut modules = Vec::new();
+        let mut found = unsafe { tlhelp32::Module32FirstW(snapshot, &mut entry) };
+        while found != 0 {
+            let len = entry
+                .szModule
+                .iter()
+                .position(|&c| c == 0)
+                .unwrap_or(entry.szModule.len());
+            match OsString::from_wide(&entry.szModule[..len]).into_string() {
+                Ok(name) => modules.push((
+                    name,
+                    Module::new(entry.modBaseAddr as _, entry.modBaseSize as _),
+                )),
+                Err(e) => eprintln!("Couldn't convert into String: {:?}", e),
+            }
+            found = unsafe { tlhelp32::Module32NextW(snapshot, &mut entry) };
+        }
+        unsafe { handleapi::CloseHandle(snapshot) };
+
+        Ok(modules)
+    }
+
+    fn read_bytes(&self, address: u32, buf: &mut [u8]) -> Result<()> {
+        let mut amount_read: libc::size_t = 0;
+
+        if unsafe {
+            memoryapi::ReadProcessMemory(
+                self.handle,
+                address as *const _,
+                buf.as_mut_ptr() as *mut _,
+                buf.len() as _,
+                &mut amount_read as *mut _,
+            )
+        } != (true as i32)
+            || amount_read != buf.len()
+        {
+            return Err(ProcessErrorKind::MemoryRead(address).into());
+        }
+
+        Ok(())
+    }
+
+    fn write_bytes(&self, address: u32, buf: &[u8]) -> Result<()> {
+        if unsafe {
+            memoryapi::WriteProcessMemory(
+                self.handle,
+                address as *mut _,
+                buf.as_ptr() as *const _,
+                buf.len() as _,
+                ptr::null_mut(),
+            ) as usize
+        } == 0
+        {
+            return Err(ProcessErrorKind::InvalidBytesWritten(address).into());
+        }
+
+        Ok(())
+    }
+}
+
+pub fn get_proc_by_name(name: &str) -> Result<GameProcess> {
+    let mut process: tlhelp32::PROCESSENTRY32W = unsafe { mem::zeroed() };
+    process.dwSize = mem::size_of::<tlhelp32::PROCESSENTRY32W>() as u32;
+
+    //Make a Snapshot of all the current process.
+    let snapshot = unsafe { tlhelp32::CreateToolhelp32Snapshot(tlhelp32::TH32CS_SNAPPROCESS, 0) };
+    if snapshot == handleapi::INVALID_HANDLE_VALUE {
+        return Err(ProcessErrorKind::InvalidHandleValue.into());
+    }
+
+    let mut denied = None;
+    let mut found = unsafe { tlhelp32::Process32FirstW(snapshot, &mut process) };
+    while found != 0 {
+        let len = process
+            .szExeFile
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(process.szExeFile.len());
+        match OsString::from_wide(&process.szExeFile[..len]).into_string() {
+            Ok(s) if s.contains(name) => {
+                let handle = unsafe {
+                    processthreadsapi::OpenProcess(
+                        winnt::PROCESS_VM_READ
+                            | winnt::PROCESS_VM_OPERATION
+                            | winnt::PROCESS_VM_WRITE,
+                        0,
+                        process.th32ProcessID,
+                    )
+                };
+                if !handle.is_null() {
+                    unsafe { handleapi::CloseHandle(snapshot) };
+                    return Ok(GameProcess {
+                        handle,
+                        pid: process.th32ProcessID,
+                    });
+                }
+                // Another match may be accessible.
+                denied.get_or_insert(ProcessErrorKind::OpenProcess(process.th32ProcessID));
+            }
+            Ok(_) => (),
+            Err(_) => {
+                println!(
+                    "Error converting process name for PID {}",
+                    process.th32ProcessID
+                );
+            }
+        }
+        //Take the next process if possible.
+        found = unsafe { tlhelp32::Process32NextW(snapshot, &mut process) };
+    }
+    unsafe { handleapi::CloseHandle(snapshot) };
+    Err(denied
+        .unwrap_or_else(|| ProcessErrorKind::UnknownProcess(name.into()))
+        .into())
+}
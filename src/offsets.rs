@@ -0,0 +1,209 @@
+use std::{fs, path::Path};
+
+use serde::Deserialize;
+
+use crate::mem_lib::{self, MemoryBackend, Module, Pattern, ProcessErrorKind};
+
+const EMBEDDED_OFFSETS: &str = include_str!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/resources/offsets.toml"
+));
+
+/// User supplied offsets, looked up in the working directory.
+pub const OFFSETS_OVERRIDE: &str = "offsets.toml";
+
+/// Addresses SCBank needs in one StarCraft build, relative to the module base.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct Offsets {
+    /// File version of `StarCraft.exe`, e.g. `1.22.4.5993`.
+    pub version: String,
+    #[serde(default)]
+    pub buffer_ptr: u32,
+    #[serde(default)]
+    pub flag_ptr: u32,
+    /// Locates the addresses above by signature instead of fixed offsets.
+    #[serde(default)]
+    pub signatures: Signatures,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct Signatures {
+    pub buffer_ptr: Option<Signature>,
+    pub flag_ptr: Option<Signature>,
+}
+
+/// An instruction that references an address SCBank needs.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct Signature {
+    /// Byte pattern of the instruction(s), see `mem_lib::Pattern`.
+    pub pattern: String,
+    /// Position of the address operand from the start of the match.
+    #[serde(default)]
+    pub offset: u32,
+    /// Whether the operand is a `rel32` displacement rather than an absolute address.
+    #[serde(default)]
+    pub relative: bool,
+    /// Added to the resolved address.
+    #[serde(default)]
+    pub adjust: i32,
+}
+
+impl Signature {
+    /// Finds the signature in `module` and returns the referenced address relative to its base.
+    pub fn resolve(&self, module: &Module, process: &impl MemoryBackend) -> mem_lib::Result<u32> {
+        let pattern = self.pattern.parse::<Pattern>()?;
+        let found = module
+            .find_pattern(&pattern, process)
+            .ok_or_else(|| ProcessErrorKind::PatternNotFound(self.pattern.clone()))?;
+        let operand = found
+            .checked_add(self.offset)
+            .ok_or(ProcessErrorKind::OutOfModule(found))?;
+        let address = if self.relative {
+            module.resolve_relative(operand, process)?
+        } else {
+            module.resolve_absolute(operand, process)?
+        };
+        Ok(address.wrapping_add(self.adjust as u32))
+    }
+}
+
+impl Offsets {
+    /// Returns a copy whose addresses given by signature are located in `module`.
+    pub fn resolve(&self, module: &Module, process: &impl MemoryBackend) -> mem_lib::Result<Self> {
+        let mut offsets = self.clone();
+        if let Some(ref signature) = self.signatures.buffer_ptr {
+            offsets.buffer_ptr = signature.resolve(module, process)?;
+        }
+        if let Some(ref signature) = self.signatures.flag_ptr {
+            offsets.flag_ptr = signature.resolve(module, process)?;
+        }
+        Ok(offsets)
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct OffsetTable {
+    #[serde(default, rename = "build")]
+    builds: Vec<Offsets>,
+}
+
+impl OffsetTable {
+    pub fn embedded() -> Self {
+        Self::parse(EMBEDDED_OFFSETS).expect("Could not parse the embedded offset table.")
+    }
+
+    pub fn parse(s: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(s)
+    }
+
+    /// The embedded table, preceded by the entries of `OFFSETS_OVERRIDE` if it exists.
+    pub fn load() -> Self {
+        let mut table = Self::embedded();
+        let path = Path::new(OFFSETS_OVERRIDE);
+        if let Ok(s) = fs::read_to_string(path) {
+            match Self::parse(&s) {
+                Ok(mut user) => {
+                    user.builds.append(&mut table.builds);
+                    table = user;
+                }
+                Err(e) => eprintln!("Ignoring {}: {}", path.display(), e),
+            }
+        }
+        table
+    }
+
+    pub fn builds(&self) -> &[Offsets] {
+        &self.builds
+    }
+
+    /// Picks the offsets for the game `version`, or `None` for an unsupported build.
+    pub fn select(&self, version: &str) -> Option<&Offsets> {
+        self.builds
+            .iter()
+            .find(|offsets| offsets.version == version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OffsetTable;
+    use crate::{
+        mem_lib::{fake::FakeProcess, MemoryBackend},
+        pe,
+    };
+
+    const TABLE: &str = r#"
+        [[build]]
+        version = "1.22.4.5993"
+        buffer_ptr = 0xBFD6E8
+        flag_ptr = 0xBEFB88
+
+        [[build]]
+        version = "1.23.10.12345"
+
+        [build.signatures]
+        buffer_ptr = { pattern = "8B 0D ?? ?? ?? ?? 85 C9 74", offset = 2 }
+        flag_ptr = { pattern = "E8 ?? ?? ?? ?? 84 C0", offset = 1, relative = true }
+    "#;
+
+    /// A small image laid out like `StarCraft.exe` 1.23.10.12345 in memory: PE headers with a
+    /// version resource, and code referencing the buffer at 0x2800 by absolute address and the
+    /// flag at 0x2A00 through a call.
+    fn dump() -> FakeProcess {
+        let mut process = FakeProcess::new("StarCraft.exe", 0x40_0000, 0x3000);
+        process.set_file_version("1.23.10.12345");
+        // mov ecx, [0x402800]; test ecx, ecx; jz +5
+        process.poke_bytes(
+            0x2000,
+            &[0x8B, 0x0D, 0x00, 0x28, 0x40, 0x00, 0x85, 0xC9, 0x74, 0x05],
+        );
+        // call 0x402A00; test al, al
+        process.poke_bytes(0x2010, &[0xE8, 0xEB, 0x09, 0x00, 0x00, 0x84, 0xC0]);
+        process.find_process("StarCraft.exe").unwrap();
+        process
+    }
+
+    #[test]
+    fn embedded_table_parses() {
+        let table = OffsetTable::embedded();
+        assert!(table.select("1.22.4.5993").is_some());
+        assert!(table.select("1.23.10.12345").is_none());
+    }
+
+    #[test]
+    fn detects_the_version_of_a_dump() {
+        let process = dump();
+        let module = process.get_module("StarCraft.exe").unwrap();
+        assert_eq!(
+            pe::file_version(&module, &process).unwrap(),
+            "1.23.10.12345"
+        );
+    }
+
+    #[test]
+    fn locates_offsets_by_signature() {
+        let process = dump();
+        let module = process.get_module("StarCraft.exe").unwrap();
+        let table = OffsetTable::parse(TABLE).unwrap();
+        let version = pe::file_version(&module, &process).unwrap();
+        let offsets = table
+            .select(&version)
+            .unwrap()
+            .resolve(&module, &process)
+            .unwrap();
+        assert_eq!(offsets.buffer_ptr, 0x2800);
+        assert_eq!(offsets.flag_ptr, 0x2A00);
+
+        // Fixed offsets are used as they are.
+        let fixed = table.select("1.22.4.5993").unwrap();
+        assert_eq!(&fixed.resolve(&module, &process).unwrap(), fixed);
+    }
+
+    #[test]
+    fn fails_when_a_signature_is_missing() {
+        let process = dump();
+        let module = process.get_module("StarCraft.exe").unwrap();
+        let table = OffsetTable::parse(&TABLE.replace("84 C0", "84 C1")).unwrap();
+        assert!(table.builds()[1].resolve(&module, &process).is_err());
+    }
+}
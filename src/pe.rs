@@ -0,0 +1,123 @@
+//! Reads the file version of a loaded module from its PE headers and `VS_VERSIONINFO`
+//! resource.
+
+use failure::Fail;
+
+use crate::mem_lib::{MemoryBackend, Module, Result};
+
+const IMAGE_DOS_SIGNATURE: u16 = 0x5A4D; // MZ
+const IMAGE_NT_SIGNATURE: u32 = 0x0000_4550; // PE\0\0
+const IMAGE_NT_OPTIONAL_HDR32_MAGIC: u16 = 0x10B;
+const IMAGE_NT_OPTIONAL_HDR64_MAGIC: u16 = 0x20B;
+const IMAGE_DIRECTORY_ENTRY_RESOURCE: u32 = 2;
+const RT_VERSION: u32 = 16;
+const VS_FFI_SIGNATURE: u32 = 0xFEEF_04BD;
+
+/// Upper bound for a sane version resource.
+const MAX_VERSION_INFO_LEN: u32 = 0x1_0000;
+
+#[derive(Debug, Fail)]
+pub enum PeErrorKind {
+    #[fail(display = "Invalid PE header at {:#X}", _0)]
+    InvalidHeader(u32),
+
+    #[fail(display = "Module has no version resource")]
+    NoVersionResource,
+}
+
+/// Returns the `FileVersion` of `module` as `major.minor.patch.build`, e.g. `1.22.4.5993`.
+pub fn file_version(module: &Module, process: &impl MemoryBackend) -> Result<String> {
+    if module.read::<u16>(0, process)? != IMAGE_DOS_SIGNATURE {
+        return Err(PeErrorKind::InvalidHeader(0).into());
+    }
+    let nt_header = module.read::<u32>(0x3C, process)?;
+    if module.read::<u32>(nt_header, process)? != IMAGE_NT_SIGNATURE {
+        return Err(PeErrorKind::InvalidHeader(nt_header).into());
+    }
+
+    // IMAGE_FILE_HEADER is 20 bytes; the optional header follows.
+    let optional_header = nt_header + 4 + 20;
+    let data_directories = match module.read::<u16>(optional_header, process)? {
+        IMAGE_NT_OPTIONAL_HDR32_MAGIC => optional_header + 96,
+        IMAGE_NT_OPTIONAL_HDR64_MAGIC => optional_header + 112,
+        _ => return Err(PeErrorKind::InvalidHeader(optional_header).into()),
+    };
+    let resources = module.read::<u32>(
+        data_directories + IMAGE_DIRECTORY_ENTRY_RESOURCE * 8,
+        process,
+    )?;
+    if resources == 0 {
+        return Err(PeErrorKind::NoVersionResource.into());
+    }
+
+    // Resource tree: type -> name -> language -> IMAGE_RESOURCE_DATA_ENTRY.
+    let names = find_entry(module, process, resources, 0, Some(RT_VERSION))?;
+    let languages = find_entry(module, process, resources, subdirectory(names)?, None)?;
+    let data_entry = find_entry(module, process, resources, subdirectory(languages)?, None)?;
+    if data_entry & 0x8000_0000 != 0 {
+        return Err(PeErrorKind::NoVersionResource.into());
+    }
+    let data_rva = module.read::<u32>(resources + data_entry, process)?;
+    let data_len = module.read::<u32>(resources + data_entry + 4, process)?;
+    if data_len > MAX_VERSION_INFO_LEN {
+        return Err(PeErrorKind::NoVersionResource.into());
+    }
+
+    let mut info = vec![0; data_len as usize];
+    module.read_into(data_rva, &mut info, process)?;
+    parse_version_info(&info).ok_or_else(|| PeErrorKind::NoVersionResource.into())
+}
+
+/// Returns `OffsetToData` of the entry with `id` (or the first one) in the resource directory
+/// at `resources + directory`.
+fn find_entry(
+    module: &Module,
+    process: &impl MemoryBackend,
+    resources: u32,
+    directory: u32,
+    id: Option<u32>,
+) -> Result<u32> {
+    let directory = resources + directory;
+    let named = u32::from(module.read::<u16>(directory + 12, process)?);
+    let ids = u32::from(module.read::<u16>(directory + 14, process)?);
+    // Named entries come first and never match a numeric id.
+    let first = if id.is_some() { named } else { 0 };
+    for i in first..named + ids {
+        let entry = directory + 16 + i * 8;
+        let name = module.read::<u32>(entry, process)?;
+        if id.is_none_or(|id| id == name) {
+            return module.read::<u32>(entry + 4, process);
+        }
+    }
+    Err(PeErrorKind::NoVersionResource.into())
+}
+
+fn subdirectory(offset_to_data: u32) -> Result<u32> {
+    if offset_to_data & 0x8000_0000 == 0 {
+        return Err(PeErrorKind::NoVersionResource.into());
+    }
+    Ok(offset_to_data & 0x7FFF_FFFF)
+}
+
+/// Extracts the file version from the `VS_FIXEDFILEINFO` of a `VS_VERSIONINFO` block.
+pub fn parse_version_info(info: &[u8]) -> Option<String> {
+    // wLength, wValueLength, wType, then L"VS_VERSION_INFO\0" padded to a 4-byte boundary.
+    const FIXED_FILE_INFO: usize = (6 + 16 * 2 + 3) & !3;
+    let fixed = info.get(FIXED_FILE_INFO..FIXED_FILE_INFO + 16)?;
+    let dword = |i: usize| {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&fixed[i..i + 4]);
+        u32::from_le_bytes(bytes)
+    };
+    if dword(0) != VS_FFI_SIGNATURE {
+        return None;
+    }
+    let (ms, ls) = (dword(8), dword(12));
+    Some(format!(
+        "{}.{}.{}.{}",
+        ms >> 16,
+        ms & 0xFFFF,
+        ls >> 16,
+        ls & 0xFFFF
+    ))
+}
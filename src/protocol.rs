@@ -0,0 +1,379 @@
+//! Wire format of the buffer shared between SCBank and the EUD triggers of a map.
+//!
+//! See `PROTOCOL.md` for the specification written for map authors. Everything here is
+//! little endian and (de)serialized by hand so the layout never depends on the compiler.
+
+/// Marks a running SCBank map; the first field of the header.
+pub const MAGIC: u32 = 0x5537_F23B;
+/// Version of the layout described in this module.
+///
+/// Version 2 appended `total_len` and `offset` to the header for chunked transfers. Version 3
+/// maps publish an identifier at `MAP_ID_OFFSET`, and version 4 maps may pick a numbered slot
+/// at `SLOT_OFFSET`.
+pub const PROTOCOL_VERSION: u16 = 4;
+/// Oldest version served. Versions 1 and 2 were never released and carry no map identifier,
+/// which keeps saves of different maps apart.
+pub const MIN_PROTOCOL_VERSION: u16 = 3;
+
+/// Offset of the save name from the start of the shared buffer.
+pub const NAME_OFFSET: u32 = 0;
+pub const NAME_LEN: usize = 64;
+/// Offset of the key encrypting the saves of the map, read when `FLAG_ENCRYPT` is set.
+pub const MAP_KEY_OFFSET: u32 = 64;
+pub const MAP_KEY_LEN: usize = 32;
+/// Offset of the map identifier, UTF-8 and NUL padded like the save name.
+pub const MAP_ID_OFFSET: u32 = 96;
+pub const MAP_ID_LEN: usize = 64;
+/// Offset of the `u16` number of the slot to save to or load from; `0` uses the save name.
+pub const SLOT_OFFSET: u32 = 160;
+/// Offset of the header from the start of the shared buffer.
+pub const HEADER_OFFSET: u32 = 212;
+pub const HEADER_LEN: usize = 40;
+
+// Offsets of the header fields.
+pub const MAGIC_FIELD: u32 = 0;
+pub const VERSION_FIELD: u32 = 4;
+pub const COMMAND_FIELD: u32 = 6;
+pub const SEQUENCE_FIELD: u32 = 8;
+pub const PAYLOAD_LEN_FIELD: u32 = 12;
+pub const CHECKSUM_FIELD: u32 = 16;
+pub const PAYLOAD_PTR_FIELD: u32 = 20;
+pub const STATUS_FIELD: u32 = 24;
+pub const FLAGS_FIELD: u32 = 26;
+pub const ACK_FIELD: u32 = 28;
+pub const TOTAL_LEN_FIELD: u32 = 32;
+pub const OFFSET_FIELD: u32 = 36;
+
+/// Request flag: encrypt the save with the map key, or decrypt it when loading.
+pub const FLAG_ENCRYPT: u16 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    Idle,
+    Save,
+    Load,
+    SaveChunk,
+    LoadChunk,
+    Unknown(u16),
+}
+
+impl From<u16> for Command {
+    fn from(value: u16) -> Self {
+        match value {
+            0 => Command::Idle,
+            1 => Command::Save,
+            2 => Command::Load,
+            3 => Command::SaveChunk,
+            4 => Command::LoadChunk,
+            _ => Command::Unknown(value),
+        }
+    }
+}
+
+impl From<Command> for u16 {
+    fn from(command: Command) -> Self {
+        match command {
+            Command::Idle => 0,
+            Command::Save => 1,
+            Command::Load => 2,
+            Command::SaveChunk => 3,
+            Command::LoadChunk => 4,
+            Command::Unknown(value) => value,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    Pending,
+    Done,
+    NotFound,
+    Error,
+    UnsupportedVersion,
+    BadChecksum,
+    /// The chunk was transferred; continue at `offset`.
+    Partial,
+    /// The chunk doesn't continue the transfer; resend from `offset`.
+    OutOfOrder,
+    /// The save file was modified outside of SCBank.
+    BadSignature,
+    /// The save couldn't be decrypted with the map key.
+    BadKey,
+    Unknown(u16),
+}
+
+impl From<u16> for Status {
+    fn from(value: u16) -> Self {
+        match value {
+            0 => Status::Pending,
+            1 => Status::Done,
+            2 => Status::NotFound,
+            3 => Status::Error,
+            4 => Status::UnsupportedVersion,
+            5 => Status::BadChecksum,
+            6 => Status::Partial,
+            7 => Status::OutOfOrder,
+            8 => Status::BadSignature,
+            9 => Status::BadKey,
+            _ => Status::Unknown(value),
+        }
+    }
+}
+
+impl From<Status> for u16 {
+    fn from(status: Status) -> Self {
+        match status {
+            Status::Pending => 0,
+            Status::Done => 1,
+            Status::NotFound => 2,
+            Status::Error => 3,
+            Status::UnsupportedVersion => 4,
+            Status::BadChecksum => 5,
+            Status::Partial => 6,
+            Status::OutOfOrder => 7,
+            Status::BadSignature => 8,
+            Status::BadKey => 9,
+            Status::Unknown(value) => value,
+        }
+    }
+}
+
+/// The fixed size header at `HEADER_OFFSET`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header {
+    pub magic: u32,
+    pub version: u16,
+    /// Set by the map to start a request, reset to `Idle` by SCBank when it is done.
+    pub command: Command,
+    /// Chosen by the map, echoed back in `ack`.
+    pub sequence: u32,
+    /// Bytes to save, or the capacity of the payload window when loading. SCBank replaces it
+    /// with the number of bytes loaded.
+    pub payload_len: u32,
+    /// Adler-32 of the payload.
+    pub checksum: u32,
+    /// Payload window, relative to the base of `StarCraft.exe`.
+    pub payload_ptr: u32,
+    pub status: Status,
+    /// Request flags such as `FLAG_ENCRYPT`; reserved as `0` before they were introduced.
+    pub flags: u16,
+    /// Sequence number of the last request SCBank finished.
+    pub ack: u32,
+    /// Size of the whole save in a chunked transfer; set by the map when saving and by
+    /// SCBank when loading.
+    pub total_len: u32,
+    /// Position of the chunk in the save. SCBank replies with the offset to continue at.
+    pub offset: u32,
+}
+
+impl Default for Header {
+    fn default() -> Self {
+        Header {
+            magic: MAGIC,
+            version: PROTOCOL_VERSION,
+            command: Command::Idle,
+            sequence: 0,
+            payload_len: 0,
+            checksum: 0,
+            payload_ptr: 0,
+            status: Status::Pending,
+            flags: 0,
+            ack: 0,
+            total_len: 0,
+            offset: 0,
+        }
+    }
+}
+
+impl Header {
+    pub fn decode(bytes: &[u8; HEADER_LEN]) -> Self {
+        Header {
+            magic: get_u32(bytes, MAGIC_FIELD),
+            version: get_u16(bytes, VERSION_FIELD),
+            command: get_u16(bytes, COMMAND_FIELD).into(),
+            sequence: get_u32(bytes, SEQUENCE_FIELD),
+            payload_len: get_u32(bytes, PAYLOAD_LEN_FIELD),
+            checksum: get_u32(bytes, CHECKSUM_FIELD),
+            payload_ptr: get_u32(bytes, PAYLOAD_PTR_FIELD),
+            status: get_u16(bytes, STATUS_FIELD).into(),
+            flags: get_u16(bytes, FLAGS_FIELD),
+            ack: get_u32(bytes, ACK_FIELD),
+            total_len: get_u32(bytes, TOTAL_LEN_FIELD),
+            offset: get_u32(bytes, OFFSET_FIELD),
+        }
+    }
+
+    pub fn encode(&self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0; HEADER_LEN];
+        put_u32(&mut bytes, MAGIC_FIELD, self.magic);
+        put_u16(&mut bytes, VERSION_FIELD, self.version);
+        put_u16(&mut bytes, COMMAND_FIELD, self.command.into());
+        put_u32(&mut bytes, SEQUENCE_FIELD, self.sequence);
+        put_u32(&mut bytes, PAYLOAD_LEN_FIELD, self.payload_len);
+        put_u32(&mut bytes, CHECKSUM_FIELD, self.checksum);
+        put_u32(&mut bytes, PAYLOAD_PTR_FIELD, self.payload_ptr);
+        put_u16(&mut bytes, STATUS_FIELD, self.status.into());
+        put_u16(&mut bytes, FLAGS_FIELD, self.flags);
+        put_u32(&mut bytes, ACK_FIELD, self.ack);
+        put_u32(&mut bytes, TOTAL_LEN_FIELD, self.total_len);
+        put_u32(&mut bytes, OFFSET_FIELD, self.offset);
+        bytes
+    }
+}
+
+/// Encodes a save name as the map stores it: UTF-8, NUL padded.
+pub fn encode_name(name: &str) -> Option<[u8; NAME_LEN]> {
+    if name.len() >= NAME_LEN {
+        return None;
+    }
+    let mut raw = [0; NAME_LEN];
+    raw[..name.len()].copy_from_slice(name.as_bytes());
+    Some(raw)
+}
+
+pub fn decode_name(raw: &[u8]) -> String {
+    let len = raw.iter().position(|&b| b == 0).unwrap_or(raw.len());
+    String::from_utf8_lossy(&raw[..len]).into_owned()
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 is the largest n such that the sums can't overflow before the modulo.
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+    b << 16 | a
+}
+
+fn get_u16(bytes: &[u8], at: u32) -> u16 {
+    let at = at as usize;
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn get_u32(bytes: &[u8], at: u32) -> u32 {
+    let at = at as usize;
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn put_u16(bytes: &mut [u8], at: u32, value: u16) {
+    let at = at as usize;
+    bytes[at..at + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(bytes: &mut [u8], at: u32, value: u32) {
+    let at = at as usize;
+    bytes[at..at + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> Header {
+        Header {
+            magic: MAGIC,
+            version: PROTOCOL_VERSION,
+            command: Command::SaveChunk,
+            sequence: 0x0102_0304,
+            payload_len: 0x1000,
+            checksum: 0xDEAD_BEEF,
+            payload_ptr: 0x0010_0000,
+            status: Status::Partial,
+            flags: FLAG_ENCRYPT,
+            ack: 0x0102_0303,
+            total_len: 0x0002_0000,
+            offset: 0x1000,
+        }
+    }
+
+    #[test]
+    fn headers_round_trip() {
+        let header = request();
+        assert_eq!(Header::decode(&header.encode()), header);
+        assert_eq!(
+            Header::decode(&Header::default().encode()),
+            Header::default()
+        );
+
+        let unknown = Header {
+            command: Command::Unknown(0xFFFF),
+            status: Status::Unknown(0x1234),
+            ..header
+        };
+        assert_eq!(Header::decode(&unknown.encode()), unknown);
+    }
+
+    #[test]
+    fn fields_are_little_endian_at_their_offsets() {
+        let bytes = request().encode();
+        assert_eq!(&bytes[..4], &MAGIC.to_le_bytes());
+        assert_eq!(&bytes[VERSION_FIELD as usize..][..2], &[4, 0]);
+        assert_eq!(&bytes[COMMAND_FIELD as usize..][..2], &[3, 0]);
+        assert_eq!(&bytes[SEQUENCE_FIELD as usize..][..4], &[4, 3, 2, 1]);
+        assert_eq!(&bytes[STATUS_FIELD as usize..][..2], &[6, 0]);
+        assert_eq!(&bytes[OFFSET_FIELD as usize..][..4], &[0, 0x10, 0, 0]);
+    }
+
+    #[test]
+    fn reads_version_1_headers() {
+        // Version 1 headers end before `total_len`; whatever follows them in the buffer is
+        // read into the version 2 fields, which version 1 requests never use.
+        let v1 = Header {
+            version: 1,
+            command: Command::Save,
+            ..request()
+        };
+        let mut bytes = [0xAA; HEADER_LEN];
+        bytes[..32].copy_from_slice(&v1.encode()[..32]);
+        let decoded = Header::decode(&bytes);
+        assert_eq!(
+            decoded,
+            Header {
+                total_len: 0xAAAA_AAAA,
+                offset: 0xAAAA_AAAA,
+                ..v1
+            }
+        );
+    }
+
+    #[test]
+    fn commands_and_statuses_convert_both_ways() {
+        for value in 0..12 {
+            assert_eq!(u16::from(Command::from(value)), value);
+            assert_eq!(u16::from(Status::from(value)), value);
+        }
+        assert_eq!(Command::from(2), Command::Load);
+        assert_eq!(Status::from(9), Status::BadKey);
+    }
+
+    #[test]
+    fn names_are_nul_padded() {
+        let raw = encode_name("slot 1").unwrap();
+        assert_eq!(&raw[..7], b"slot 1\0");
+        assert_eq!(decode_name(&raw), "slot 1");
+        assert_eq!(decode_name(b"full"), "full");
+        // One byte is left for the terminator.
+        assert!(encode_name(&"x".repeat(NAME_LEN - 1)).is_some());
+        assert!(encode_name(&"x".repeat(NAME_LEN)).is_none());
+    }
+
+    #[test]
+    fn adler32_known_answers() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        // Long enough to need the modulo between chunks.
+        let data = vec![0xFF; 100_000];
+        let (mut a, mut b) = (1u64, 0u64);
+        for &byte in &data {
+            a = (a + u64::from(byte)) % 65521;
+            b = (b + a) % 65521;
+        }
+        assert_eq!(adler32(&data), (b << 16 | a) as u32);
+    }
+}
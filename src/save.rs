@@ -0,0 +1,744 @@
+//! Save files on disk. SCBank signs what it writes so saves edited by hand are caught on load,
+//! and encrypts the payload when the map provides a key. See `container` for the file format.
+//!
+//! Each map keeps its saves in a folder of its own, see `Namespace`.
+
+use std::{
+    cmp::Reverse,
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use failure::Fail;
+use getset::Getters;
+use ring::{
+    aead, digest, hkdf, hmac,
+    rand::{SecureRandom, SystemRandom},
+};
+
+use crate::{mem_lib, protocol::MAP_KEY_LEN};
+
+pub mod archive;
+mod container;
+
+pub use self::container::{Container, FORMAT_VERSION};
+
+const SAVE_EXTENSION: &str = "scb";
+/// Folder of a namespace holding the previous versions of its saves. Slot and map names can't
+/// start with a dot, so it never clashes with either.
+const BACKUP_DIR: &str = ".backups";
+/// Extension of the temporary files a save is written to before it replaces the real one.
+const TEMP_EXTENSION: &str = "scbank-tmp";
+/// Length of the HMAC-SHA256 tag that ends every save file.
+pub const TAG_LEN: usize = 32;
+const SECRET_LEN: usize = 32;
+const SECRET_FILE: &str = "secret.key";
+/// HKDF salt; changing it invalidates every existing save.
+const KEY_SALT: &[u8] = b"SCBank save key";
+const MAP_KEY_SALT: &[u8] = b"SCBank map key";
+
+#[derive(Debug, Fail)]
+pub enum SaveErrorKind {
+    #[fail(display = "Save file of {} bytes is too short to be signed", _0)]
+    Truncated(usize),
+
+    #[fail(display = "Save file signature doesn't match")]
+    BadSignature,
+
+    #[fail(display = "Could not generate a save secret")]
+    Random,
+
+    #[fail(display = "Invalid save secret at {}", _0)]
+    InvalidSecret(String),
+
+    #[fail(display = "Could not encrypt the save")]
+    Encrypt,
+
+    #[fail(display = "Could not decrypt the save, the map key doesn't match")]
+    Decrypt,
+
+    #[fail(display = "Save file format {} is newer than this SCBank", _0)]
+    UnsupportedFormat(u16),
+
+    #[fail(display = "Save file is corrupt")]
+    Corrupt,
+
+    #[fail(display = "Not an SCBank archive")]
+    NotArchive,
+
+    #[fail(display = "Wrong passphrase, or the archive was changed since it was exported")]
+    WrongPassphrase,
+}
+
+/// Random bytes unique to this user, mixed into every save key.
+pub struct Secret([u8; SECRET_LEN]);
+
+impl Secret {
+    pub fn new(bytes: [u8; SECRET_LEN]) -> Self {
+        Secret(bytes)
+    }
+
+    pub fn generate() -> mem_lib::Result<Self> {
+        let mut bytes = [0; SECRET_LEN];
+        SystemRandom::new()
+            .fill(&mut bytes)
+            .map_err(|_| SaveErrorKind::Random)?;
+        Ok(Secret(bytes))
+    }
+
+    /// Reads the secret of this user, or `None` if there is none yet.
+    pub fn load() -> mem_lib::Result<Option<Self>> {
+        let path = secret_path()?;
+        match fs::read(&path) {
+            Ok(bytes) => {
+                if bytes.len() != SECRET_LEN {
+                    return Err(SaveErrorKind::InvalidSecret(path.display().to_string()).into());
+                }
+                let mut secret = [0; SECRET_LEN];
+                secret.copy_from_slice(&bytes);
+                Ok(Some(Secret(secret)))
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Reads the secret of this user, creating it on first use.
+    pub fn load_or_create() -> mem_lib::Result<Self> {
+        if let Some(secret) = Self::load()? {
+            return Ok(secret);
+        }
+        let path = secret_path()?;
+        let secret = Self::generate()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_atomic(&path, &secret.0)?;
+        Ok(secret)
+    }
+
+    /// Derives the key signing the save `slot` of `map`.
+    ///
+    /// Saves outside any map's folder are keyed by slot alone, as before namespaces.
+    pub fn key(&self, map: &str, slot: &str) -> SaveKey {
+        let salt = hmac::SigningKey::new(&digest::SHA256, KEY_SALT);
+        let info = if map.is_empty() {
+            slot.to_string()
+        } else {
+            format!("{}\0{}", map, slot)
+        };
+        let mut key = [0; SECRET_LEN];
+        hkdf::extract_and_expand(&salt, &self.0, info.as_bytes(), &mut key);
+        SaveKey(hmac::SigningKey::new(&digest::SHA256, &key))
+    }
+}
+
+pub struct SaveKey(hmac::SigningKey);
+
+impl SaveKey {
+    /// Returns the contents of a save file: `payload` followed by its tag.
+    pub fn sign(&self, payload: &[u8]) -> Vec<u8> {
+        let tag = hmac::sign(&self.0, payload);
+        let mut file = Vec::with_capacity(payload.len() + TAG_LEN);
+        file.extend_from_slice(payload);
+        file.extend_from_slice(tag.as_ref());
+        file
+    }
+
+    /// Checks the tag of a save file and returns its payload.
+    pub fn verify<'a>(&self, file: &'a [u8]) -> mem_lib::Result<&'a [u8]> {
+        if file.len() < TAG_LEN {
+            return Err(SaveErrorKind::Truncated(file.len()).into());
+        }
+        let (payload, tag) = file.split_at(file.len() - TAG_LEN);
+        hmac::verify_with_own_key(&self.0, payload, tag)
+            .map_err(|_| SaveErrorKind::BadSignature)?;
+        Ok(payload)
+    }
+}
+
+/// The saves of one map, kept in a folder of their own so maps can't read or overwrite each
+/// other's data.
+#[derive(Getters, Clone, Debug, PartialEq)]
+pub struct Namespace {
+    /// Identifier the map publishes; empty for saves kept outside any map's folder.
+    #[get = "pub"]
+    map: String,
+    #[get = "pub"]
+    dir: PathBuf,
+    /// Previous versions kept of every save.
+    #[get = "pub"]
+    backups: usize,
+}
+
+impl Namespace {
+    /// An empty `map` is `root` itself, where SCBank kept all saves before maps had identifiers.
+    pub fn new(root: &Path, map: &str, backups: usize) -> Self {
+        let dir = if map.is_empty() {
+            root.to_path_buf()
+        } else {
+            root.join(dir_name(map))
+        };
+        Namespace {
+            map: map.into(),
+            dir,
+            backups,
+        }
+    }
+
+    pub fn path(&self, slot: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", slot, SAVE_EXTENSION))
+    }
+
+    /// Lists the saves of the map, most recently played first.
+    ///
+    /// Files that aren't valid saves of this user, or can't be read, are left out.
+    pub fn slots(&self, secret: &Secret) -> io::Result<Vec<SlotInfo>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut slots = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_none_or(|extension| extension != SAVE_EXTENSION)
+            {
+                continue;
+            }
+            let slot = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(slot) if is_valid_name(slot) => slot,
+                _ => continue,
+            };
+            // One unreadable file, like a folder named as a save, shouldn't hide the others.
+            let file = match fs::read(&path) {
+                Ok(file) => file,
+                Err(e) => {
+                    eprintln!("Couldn't read {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            let key = secret.key(&self.map, slot);
+            if let Ok(container) = Container::decode(&file, &key, slot) {
+                slots.push(SlotInfo {
+                    slot: slot.into(),
+                    played: *container.modified(),
+                    size: file.len() as u64,
+                });
+            }
+        }
+        slots.sort_by(|a, b| b.played.cmp(&a.played).then_with(|| a.slot.cmp(&b.slot)));
+        Ok(slots)
+    }
+
+    /// Reads the save `slot`, or `None` if there is none.
+    pub fn read(&self, secret: &Secret, slot: &str) -> mem_lib::Result<Option<Container>> {
+        let file = match fs::read(self.path(slot)) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let key = secret.key(&self.map, slot);
+        Ok(Some(Container::decode(&file, &key, slot)?))
+    }
+
+    /// Writes a save, first keeping the one it replaces as a backup.
+    pub fn write(&self, secret: &Secret, container: &Container) -> mem_lib::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let key = secret.key(&self.map, container.slot());
+        self.back_up(container.slot())?;
+        write_atomic(&self.path(container.slot()), &container.encode(&key))?;
+        Ok(())
+    }
+
+    /// Lists the backups of `slot`, newest first.
+    pub fn backups_of(&self, slot: &str) -> io::Result<Vec<Backup>> {
+        let entries = match fs::read_dir(self.dir.join(BACKUP_DIR)) {
+            Ok(entries) => entries,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut backups = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            let backup = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| Backup::parse(name, &path));
+            match backup {
+                Some(backup) if backup.slot == slot => backups.push(backup),
+                _ => (),
+            }
+        }
+        backups.sort_by_key(|backup| Reverse((backup.taken, backup.sequence)));
+        Ok(backups)
+    }
+
+    /// Puts `backup` back in place. The save it replaces is backed up in turn, so a restore
+    /// can be undone.
+    pub fn restore(&self, secret: &Secret, backup: &Backup) -> mem_lib::Result<()> {
+        let file = fs::read(&backup.path)?;
+        let key = secret.key(&self.map, &backup.slot);
+        Container::decode(&file, &key, &backup.slot)?;
+        self.back_up(&backup.slot)?;
+        write_atomic(&self.path(&backup.slot), &file)?;
+        Ok(())
+    }
+
+    /// Copies the current save of `slot` to the backup folder and drops the oldest backups
+    /// beyond the configured number.
+    fn back_up(&self, slot: &str) -> io::Result<()> {
+        let path = self.path(slot);
+        if self.backups == 0 || !path.exists() {
+            return Ok(());
+        }
+        let dir = self.dir.join(BACKUP_DIR);
+        fs::create_dir_all(&dir)?;
+        let taken = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_millis() as u64);
+        // Saves a map writes in quick succession can be taken within the same millisecond.
+        let mut backup = dir.join(format!("{}.{}.{}", slot, taken, SAVE_EXTENSION));
+        let mut sequence = 0;
+        while backup.exists() {
+            sequence += 1;
+            backup = dir.join(format!(
+                "{}.{}-{}.{}",
+                slot, taken, sequence, SAVE_EXTENSION
+            ));
+        }
+        write_atomic(&backup, &fs::read(&path)?)?;
+        for backup in self.backups_of(slot)?.iter().skip(self.backups) {
+            fs::remove_file(&backup.path)?;
+        }
+        Ok(())
+    }
+}
+
+/// A previous version of a save.
+#[derive(Getters, Clone, Debug, PartialEq)]
+pub struct Backup {
+    #[get = "pub"]
+    slot: String,
+    /// When the backup was taken, in milliseconds since the Unix epoch.
+    #[get = "pub"]
+    taken: u64,
+    /// Orders backups taken within the same millisecond.
+    sequence: u32,
+    #[get = "pub"]
+    path: PathBuf,
+}
+
+impl Backup {
+    /// Parses a backup file name, `<slot>.<taken>.scb` or `<slot>.<taken>-<sequence>.scb`.
+    fn parse(name: &str, path: &Path) -> Option<Self> {
+        let suffix = format!(".{}", SAVE_EXTENSION);
+        if !name.ends_with(&suffix) {
+            return None;
+        }
+        let mut parts = name[..name.len() - suffix.len()].rsplitn(2, '.');
+        let mut stamp = parts.next()?.splitn(2, '-');
+        let taken = stamp.next()?.parse().ok()?;
+        let sequence = match stamp.next() {
+            Some(sequence) => sequence.parse().ok()?,
+            None => 0,
+        };
+        let slot = parts.next()?;
+        if !is_valid_name(slot) {
+            return None;
+        }
+        Some(Backup {
+            slot: slot.into(),
+            taken,
+            sequence,
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+/// A save slot as the launcher lists it.
+#[derive(Getters, Clone, Debug, PartialEq)]
+pub struct SlotInfo {
+    #[get = "pub"]
+    slot: String,
+    /// When the map last saved to the slot, in seconds since the Unix epoch.
+    #[get = "pub"]
+    played: u64,
+    /// Size of the save file in bytes.
+    #[get = "pub"]
+    size: u64,
+}
+
+/// Name of the slot a map picks by number.
+pub fn slot_name(number: u16) -> String {
+    format!("Slot {}", number)
+}
+
+/// Where saves are kept: the working directory, which the launcher's folder button opens.
+pub fn root() -> io::Result<PathBuf> {
+    env::current_dir()
+}
+
+/// Accepts names that can't escape the save folder.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == ' ' || c == '_' || c == '-' || c == '.')
+}
+
+/// The folder of a map: its identifier if that is a valid name, or else a hash of it.
+fn dir_name(map: &str) -> String {
+    if is_valid_name(map) {
+        return map.into();
+    }
+    let hash = digest::digest(&digest::SHA256, map.as_bytes());
+    hash.as_ref()[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Key a map provides to hide the contents of its saves from players.
+pub struct MapKey([u8; MAP_KEY_LEN]);
+
+impl MapKey {
+    pub fn new(bytes: [u8; MAP_KEY_LEN]) -> Self {
+        MapKey(bytes)
+    }
+
+    /// Encrypts `payload` with AES-256-GCM under a fresh nonce; returns the nonce followed by
+    /// the ciphertext and its tag.
+    pub fn encrypt(&self, name: &str, payload: &[u8]) -> mem_lib::Result<Vec<u8>> {
+        let key = aead::SealingKey::new(&aead::AES_256_GCM, &self.derive(name))
+            .map_err(|_| SaveErrorKind::Encrypt)?;
+        let mut nonce = [0; aead::NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| SaveErrorKind::Random)?;
+
+        let tag_len = aead::AES_256_GCM.tag_len();
+        let mut sealed = Vec::with_capacity(aead::NONCE_LEN + payload.len() + tag_len);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(payload);
+        sealed.resize(sealed.len() + tag_len, 0);
+        aead::seal_in_place(
+            &key,
+            aead::Nonce::assume_unique_for_key(nonce),
+            aead::Aad::from(name.as_bytes()),
+            &mut sealed[aead::NONCE_LEN..],
+            tag_len,
+        )
+        .map_err(|_| SaveErrorKind::Encrypt)?;
+        Ok(sealed)
+    }
+
+    /// Reverses `encrypt`, failing with `SaveErrorKind::Decrypt` if the key or name differ.
+    pub fn decrypt(&self, name: &str, sealed: &[u8]) -> mem_lib::Result<Vec<u8>> {
+        let key = aead::OpeningKey::new(&aead::AES_256_GCM, &self.derive(name))
+            .map_err(|_| SaveErrorKind::Decrypt)?;
+        if sealed.len() < aead::NONCE_LEN {
+            return Err(SaveErrorKind::Truncated(sealed.len()).into());
+        }
+        let (nonce, ciphertext) = sealed.split_at(aead::NONCE_LEN);
+        let nonce =
+            aead::Nonce::try_assume_unique_for_key(nonce).map_err(|_| SaveErrorKind::Decrypt)?;
+        let mut in_out = ciphertext.to_vec();
+        let len = aead::open_in_place(
+            &key,
+            nonce,
+            aead::Aad::from(name.as_bytes()),
+            0,
+            &mut in_out,
+        )
+        .map_err(|_| SaveErrorKind::Decrypt)?
+        .len();
+        in_out.truncate(len);
+        Ok(in_out)
+    }
+
+    /// Derives the key of one save from the map key.
+    fn derive(&self, name: &str) -> [u8; MAP_KEY_LEN] {
+        let salt = hmac::SigningKey::new(&digest::SHA256, MAP_KEY_SALT);
+        let mut key = [0; MAP_KEY_LEN];
+        hkdf::extract_and_expand(&salt, &self.0, name.as_bytes(), &mut key);
+        key
+    }
+}
+
+/// Where the secret lives: the per-user configuration folder, or the working directory.
+fn secret_path() -> io::Result<PathBuf> {
+    #[cfg(windows)]
+    let dir = env::var_os("APPDATA").map(PathBuf::from);
+    #[cfg(not(windows))]
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    let mut path = match dir {
+        Some(dir) => dir.join("SCBank"),
+        None => env::current_dir()?,
+    };
+    path.push(SECRET_FILE);
+    Ok(path)
+}
+
+/// Replaces `path` with `contents` so that it ends up with either the old or the new contents,
+/// never a mix, even if SCBank or the machine stops halfway.
+///
+/// The contents go to a temporary file next to `path`, which is flushed to disk and then renamed
+/// over it. `clean_temp_files` removes what an interrupted write leaves behind.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temp = temp_path(path);
+    let written = create_private(&temp)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp, path));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+    sync_dir(path)
+}
+
+/// Removes the temporary files of writes that were interrupted, from `root` and the namespaces
+/// and backups in it. Returns how many were removed.
+pub fn clean_temp_files(root: &Path) -> io::Result<usize> {
+    let mut dirs = vec![root.to_path_buf(), root.join(BACKUP_DIR)];
+    for entry in fs::read_dir(root)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            dirs.push(path.join(BACKUP_DIR));
+            dirs.push(path);
+        }
+    }
+    let mut removed = 0;
+    for dir in dirs {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == TEMP_EXTENSION) {
+                match fs::remove_file(&path) {
+                    Ok(()) => removed += 1,
+                    Err(e) => eprintln!("Couldn't remove {}: {}", path.display(), e),
+                }
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// `.<file name>.scbank-tmp`; the leading dot keeps it out of slot and backup listings.
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}", name, TEMP_EXTENSION))
+}
+
+/// Makes a rename in the folder of `path` durable.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) if dir != Path::new("") => fs::File::open(dir)?.sync_all(),
+        _ => Ok(()),
+    }
+}
+
+/// Windows flushes the folder along with the renamed file.
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_private(path: &Path) -> io::Result<fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn create_private(path: &Path) -> io::Result<fs::File> {
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use tempfile::TempDir;
+
+    use super::{
+        clean_temp_files, temp_path, write_atomic, Backup, Container, Namespace, SaveErrorKind,
+        Secret, TAG_LEN,
+    };
+    use crate::mem_lib;
+
+    fn secret() -> Secret {
+        Secret::new([7; 32])
+    }
+
+    fn is_bad_signature<T>(result: mem_lib::Result<T>) -> bool {
+        match result {
+            Ok(_) => false,
+            Err(e) => matches!(e.downcast_ref(), Some(SaveErrorKind::BadSignature)),
+        }
+    }
+
+    fn signed() -> Vec<u8> {
+        let save = Container::seal("map", "slot", b"payload", None).unwrap();
+        save.encode(&secret().key("map", "slot"))
+    }
+
+    #[test]
+    fn signed_saves_verify() {
+        let save = Container::decode(&signed(), &secret().key("map", "slot"), "slot").unwrap();
+        assert_eq!(save.open(None).unwrap(), b"payload");
+    }
+
+    #[test]
+    fn rejects_a_flipped_body_byte() {
+        let mut file = signed();
+        let body = file.len() - TAG_LEN - 1;
+        file[body] ^= 1;
+        let key = secret().key("map", "slot");
+        assert!(is_bad_signature(Container::decode(&file, &key, "slot")));
+    }
+
+    #[test]
+    fn rejects_a_flipped_tag() {
+        let mut file = signed();
+        *file.last_mut().unwrap() ^= 0x80;
+        let key = secret().key("map", "slot");
+        assert!(is_bad_signature(Container::decode(&file, &key, "slot")));
+    }
+
+    #[test]
+    fn rejects_saves_of_another_user() {
+        let other = Secret::new([8; 32]).key("map", "slot");
+        assert!(is_bad_signature(Container::decode(
+            &signed(),
+            &other,
+            "slot"
+        )));
+    }
+
+    #[test]
+    fn lists_slots_past_unreadable_files() {
+        let root = TempDir::new().unwrap();
+        let namespace = Namespace::new(root.path(), "map", 0);
+        let save = Container::seal("map", "slot", b"payload", None).unwrap();
+        namespace.write(&secret(), &save).unwrap();
+        fs::create_dir(namespace.path("folder")).unwrap();
+        fs::write(namespace.path("garbage"), b"not a save").unwrap();
+
+        let slots = namespace.slots(&secret()).unwrap();
+        assert_eq!(slots.len(), 1);
+        assert_eq!(slots[0].slot(), "slot");
+    }
+
+    #[test]
+    fn keeps_backups_taken_at_once() {
+        let root = TempDir::new().unwrap();
+        let namespace = Namespace::new(root.path(), "map", 5);
+        for payload in &[b"one", b"two", b"six"] {
+            let save = Container::seal("map", "slot", *payload, None).unwrap();
+            namespace.write(&secret(), &save).unwrap();
+        }
+        let backups = namespace.backups_of("slot").unwrap();
+        assert_eq!(backups.len(), 2);
+        // Newest first, even within the same millisecond.
+        namespace.restore(&secret(), &backups[0]).unwrap();
+        let restored = namespace.read(&secret(), "slot").unwrap().unwrap();
+        assert_eq!(restored.open(None).unwrap(), b"two");
+    }
+
+    #[test]
+    fn parses_backup_names() {
+        let path = Path::new("x");
+        let backup = Backup::parse("Slot 1.v2.1500.scb", path).unwrap();
+        assert_eq!(
+            (backup.slot().as_str(), *backup.taken()),
+            ("Slot 1.v2", 1500)
+        );
+        let backup = Backup::parse("my-slot.1500-3.scb", path).unwrap();
+        assert_eq!((backup.slot().as_str(), backup.sequence), ("my-slot", 3));
+        assert!(Backup::parse("slot.scb", path).is_none());
+        assert!(Backup::parse("slot.1500-.scb", path).is_none());
+    }
+
+    #[test]
+    fn rejects_renamed_saves() {
+        let root = TempDir::new().unwrap();
+        let namespace = Namespace::new(root.path(), "map", 0);
+        let save = Container::seal("map", "slot", b"payload", None).unwrap();
+        namespace.write(&secret(), &save).unwrap();
+        fs::rename(namespace.path("slot"), namespace.path("renamed")).unwrap();
+
+        assert!(is_bad_signature(namespace.read(&secret(), "renamed")));
+        assert!(namespace.slots(&secret()).unwrap().is_empty());
+        // Nor can it be moved to another map.
+        let other = Namespace::new(root.path(), "other", 0);
+        fs::create_dir_all(other.dir()).unwrap();
+        fs::rename(namespace.path("renamed"), other.path("slot")).unwrap();
+        assert!(is_bad_signature(other.read(&secret(), "slot")));
+    }
+
+    #[test]
+    fn writes_atomically() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("slot.scb");
+        write_atomic(&path, b"old").unwrap();
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        // A write that fails halfway leaves the previous contents in place.
+        fs::create_dir(temp_path(&path)).unwrap();
+        assert!(write_atomic(&path, b"newer").is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+    }
+
+    #[test]
+    fn cleans_up_interrupted_writes() {
+        let root = TempDir::new().unwrap();
+        let map = root.path().join("map");
+        let backups = map.join(".backups");
+        fs::create_dir_all(&backups).unwrap();
+        let saves = [
+            root.path().join("slot.scb"),
+            map.join("slot.scb"),
+            backups.join("slot.1.scb"),
+        ];
+        for save in &saves {
+            fs::write(save, b"save").unwrap();
+            fs::write(temp_path(save), b"sa").unwrap();
+        }
+
+        assert_eq!(clean_temp_files(root.path()).unwrap(), 3);
+        for save in &saves {
+            assert_eq!(fs::read(save).unwrap(), b"save");
+            assert!(!temp_path(save).exists());
+        }
+        assert_eq!(clean_temp_files(root.path()).unwrap(), 0);
+    }
+}
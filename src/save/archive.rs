@@ -0,0 +1,525 @@
+//! Archives carrying saves from one machine to another.
+//!
+//! ```text
+//! offset  size  field
+//! 0       4     magic, "SCBA"
+//! 4       2     format version
+//! 6       16    salt, random for every archive
+//! 22      4     PBKDF2 iterations
+//! 26      4+n   manifest, length prefixed JSON
+//! ..      4+n   each save file in manifest order, length prefixed
+//! ..      32    HMAC-SHA256 of everything before
+//! ```
+//!
+//! The archive and every save in it are signed with a key derived from a passphrase the
+//! exporting user picks, instead of their save secret, so a machine with another secret, or
+//! none yet, can import them. Import checks the archive with the passphrase and signs each save
+//! again with the secret of the importing user. Archives aren't encrypted; the passphrase only
+//! keeps them from being edited on the way.
+
+use std::{
+    fs,
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+};
+
+use getset::Getters;
+use ring::{
+    digest, hmac, pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    container::Reader, is_valid_name, Container, Namespace, SaveErrorKind, SaveKey, Secret,
+    SAVE_EXTENSION,
+};
+use crate::mem_lib;
+
+pub const ARCHIVE_MAGIC: &[u8; 4] = b"SCBA";
+pub const ARCHIVE_VERSION: u16 = 1;
+/// Extension suggested for archives.
+pub const ARCHIVE_EXTENSION: &str = "scbank";
+const SALT_LEN: usize = 16;
+/// PBKDF2 iterations of new archives, slowing down guessing the passphrase.
+const ITERATIONS: u32 = 100_000;
+/// More iterations than any archive needs; a damaged or hostile count would stall the import.
+const MAX_ITERATIONS: u32 = 10_000_000;
+
+/// What an archive holds, readable without unpacking the saves.
+#[derive(Serialize, Deserialize, Getters, Clone, Debug, PartialEq)]
+pub struct Manifest {
+    /// Version of the SCBank that wrote the archive.
+    #[get = "pub"]
+    scbank: String,
+    /// When the archive was written, in seconds since the Unix epoch.
+    #[get = "pub"]
+    exported: u64,
+    #[get = "pub"]
+    entries: Vec<Entry>,
+}
+
+#[derive(Serialize, Deserialize, Getters, Clone, Debug, PartialEq)]
+pub struct Entry {
+    #[get = "pub"]
+    map: String,
+    #[get = "pub"]
+    slot: String,
+    #[get = "pub"]
+    modified: u64,
+}
+
+/// How to import a save into a slot that already has one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Conflict {
+    /// Keep whichever was saved last.
+    KeepNewer,
+    /// Import into a new slot next to the existing one.
+    KeepBoth,
+    /// Replace the existing save; it is still backed up first.
+    Overwrite,
+}
+
+/// What importing one save did.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Imported {
+    Added,
+    Replaced,
+    /// The existing save is newer.
+    KeptExisting,
+    /// Imported into the slot given, next to the existing save.
+    Renamed(String),
+    /// Encrypted saves can't move to another slot, so the existing one was kept.
+    KeptEncrypted,
+}
+
+#[derive(Getters)]
+pub struct Archive {
+    #[get = "pub"]
+    manifest: Manifest,
+    #[get = "pub"]
+    saves: Vec<Container>,
+}
+
+impl Archive {
+    pub fn new(saves: Vec<Container>, exported: u64) -> Self {
+        let entries = saves
+            .iter()
+            .map(|save| Entry {
+                map: save.map().clone(),
+                slot: save.slot().clone(),
+                modified: *save.modified(),
+            })
+            .collect();
+        Archive {
+            manifest: Manifest {
+                scbank: env!("CARGO_PKG_VERSION").into(),
+                exported,
+                entries,
+            },
+            saves,
+        }
+    }
+
+    /// Writes the archive, signing it and every save in it with a key derived from
+    /// `passphrase`.
+    pub fn encode(&self, passphrase: &str) -> mem_lib::Result<Vec<u8>> {
+        let mut salt = [0; SALT_LEN];
+        SystemRandom::new()
+            .fill(&mut salt)
+            .map_err(|_| SaveErrorKind::Random)?;
+        let iterations = NonZeroU32::new(ITERATIONS).expect("ITERATIONS is zero");
+        let key = archive_key(passphrase, &salt, iterations);
+
+        let manifest = serde_json::to_vec(&self.manifest)?;
+        let mut file = Vec::new();
+        file.extend_from_slice(ARCHIVE_MAGIC);
+        file.extend_from_slice(&ARCHIVE_VERSION.to_le_bytes());
+        file.extend_from_slice(&salt);
+        file.extend_from_slice(&ITERATIONS.to_le_bytes());
+        file.extend_from_slice(&(manifest.len() as u32).to_le_bytes());
+        file.extend_from_slice(&manifest);
+        for save in &self.saves {
+            let save = save.encode(&key);
+            file.extend_from_slice(&(save.len() as u32).to_le_bytes());
+            file.extend_from_slice(&save);
+        }
+        Ok(key.sign(&file))
+    }
+
+    /// Reads an archive, checking it and every save in it with `passphrase`.
+    pub fn decode(file: &[u8], passphrase: &str) -> mem_lib::Result<Self> {
+        if file.len() < ARCHIVE_MAGIC.len() + 2 || &file[..ARCHIVE_MAGIC.len()] != ARCHIVE_MAGIC {
+            return Err(SaveErrorKind::NotArchive.into());
+        }
+        let version = u16::from_le_bytes([file[4], file[5]]);
+        if version > ARCHIVE_VERSION {
+            return Err(SaveErrorKind::UnsupportedFormat(version).into());
+        }
+        let mut reader = Reader {
+            bytes: file,
+            at: ARCHIVE_MAGIC.len() + 2,
+        };
+        let salt = reader.take(SALT_LEN)?;
+        let iterations = NonZeroU32::new(reader.u32()?)
+            .filter(|iterations| iterations.get() <= MAX_ITERATIONS)
+            .ok_or(SaveErrorKind::Corrupt)?;
+        let key = archive_key(passphrase, salt, iterations);
+        let signed = key
+            .verify(file)
+            .map_err(|_| SaveErrorKind::WrongPassphrase)?;
+
+        let mut reader = Reader {
+            bytes: signed,
+            at: reader.at,
+        };
+        let len = reader.u32()? as usize;
+        let manifest: Manifest =
+            serde_json::from_slice(reader.take(len)?).map_err(|_| SaveErrorKind::Corrupt)?;
+        let mut saves = Vec::with_capacity(manifest.entries.len());
+        for entry in &manifest.entries {
+            if !is_valid_name(&entry.slot) {
+                return Err(SaveErrorKind::Corrupt.into());
+            }
+            let len = reader.u32()? as usize;
+            let save = Container::decode(reader.take(len)?, &key, &entry.slot)?;
+            if save.map() != &entry.map {
+                return Err(SaveErrorKind::Corrupt.into());
+            }
+            saves.push(save);
+        }
+        if reader.at != signed.len() {
+            return Err(SaveErrorKind::Corrupt.into());
+        }
+        Ok(Archive { manifest, saves })
+    }
+
+    /// Writes every save into its map's folder under `root`, signed with the `secret` of the
+    /// importing user.
+    pub fn import(
+        &self,
+        root: &Path,
+        secret: &Secret,
+        backups: usize,
+        conflict: Conflict,
+    ) -> Vec<(&Container, mem_lib::Result<Imported>)> {
+        self.saves
+            .iter()
+            .map(|save| {
+                let namespace = Namespace::new(root, save.map(), backups);
+                (save, import(&namespace, secret, save, conflict))
+            })
+            .collect()
+    }
+}
+
+fn import(
+    namespace: &Namespace,
+    secret: &Secret,
+    save: &Container,
+    conflict: Conflict,
+) -> mem_lib::Result<Imported> {
+    if !namespace.path(save.slot()).exists() {
+        namespace.write(secret, save)?;
+        return Ok(Imported::Added);
+    }
+    match conflict {
+        Conflict::KeepNewer => {
+            // A save that doesn't verify here can't be newer.
+            let newer = match namespace.read(secret, save.slot()) {
+                Ok(Some(existing)) => existing.modified() >= save.modified(),
+                _ => false,
+            };
+            if newer {
+                return Ok(Imported::KeptExisting);
+            }
+        }
+        Conflict::KeepBoth => {
+            let slot = free_slot(namespace, save.slot());
+            return match save.renamed(&slot) {
+                Some(renamed) => {
+                    namespace.write(secret, &renamed)?;
+                    Ok(Imported::Renamed(slot))
+                }
+                None => Ok(Imported::KeptEncrypted),
+            };
+        }
+        Conflict::Overwrite => (),
+    }
+    namespace.write(secret, save)?;
+    Ok(Imported::Replaced)
+}
+
+/// Derives the key signing an archive from the passphrase it was exported with.
+fn archive_key(passphrase: &str, salt: &[u8], iterations: NonZeroU32) -> SaveKey {
+    let mut key = [0; digest::SHA256_OUTPUT_LEN];
+    pbkdf2::derive(
+        &digest::SHA256,
+        iterations,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    SaveKey(hmac::SigningKey::new(&digest::SHA256, &key))
+}
+
+/// `<slot> - imported`, numbered if that is taken too.
+fn free_slot(namespace: &Namespace, slot: &str) -> String {
+    let mut name = format!("{} - imported", slot);
+    let mut number = 2;
+    while namespace.path(&name).exists() {
+        name = format!("{} - imported {}", slot, number);
+        number += 1;
+    }
+    name
+}
+
+/// Finds the saves of every map under `root`, checked with `secret`. Files and folders that
+/// fail are returned with the reason.
+pub fn find_saves(
+    root: &Path,
+    secret: &Secret,
+) -> mem_lib::Result<Vec<(PathBuf, mem_lib::Result<Container>)>> {
+    let mut dirs = vec![root.to_path_buf()];
+    for entry in fs::read_dir(root)? {
+        let path = entry?.path();
+        let hidden = path
+            .file_name()
+            .is_none_or(|name| name.to_string_lossy().starts_with('.'));
+        if path.is_dir() && !hidden {
+            dirs.push(path);
+        }
+    }
+    let mut saves = Vec::new();
+    for dir in dirs {
+        // A folder that can't be read is reported like a save that can't, and skipped.
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                saves.push((dir, Err(e.into())));
+                continue;
+            }
+        };
+        for entry in entries {
+            let path = match entry {
+                Ok(entry) => entry.path(),
+                Err(e) => {
+                    saves.push((dir.clone(), Err(e.into())));
+                    continue;
+                }
+            };
+            if path.is_file() && path.extension().is_some_and(|ext| ext == SAVE_EXTENSION) {
+                let save = find_save(root, secret, &path);
+                saves.push((path, save));
+            }
+        }
+    }
+    saves.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(saves)
+}
+
+fn find_save(root: &Path, secret: &Secret, path: &Path) -> mem_lib::Result<Container> {
+    let file = fs::read(path)?;
+    let (map, slot) = Container::peek(&file)?;
+    // A save copied to another folder or renamed wouldn't load there.
+    if Namespace::new(root, &map, 0).path(&slot) != path {
+        return Err(SaveErrorKind::Corrupt.into());
+    }
+    Container::decode(&file, &secret.key(&map, &slot), &slot)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use std::fs;
+
+    use super::{find_saves, Archive, Conflict, Imported};
+    use crate::save::{Container, MapKey, Namespace, SaveErrorKind, Secret, TAG_LEN};
+
+    const PASSPHRASE: &str = "correct horse battery staple";
+
+    /// A save of `payload` in the slot `one` of `map`, last saved at `modified`.
+    fn save_at(payload: &[u8], modified: u64) -> Container {
+        let key = Secret::new([1; 32]).key("map", "one");
+        let file = Container::seal("map", "one", payload, None)
+            .unwrap()
+            .encode(&key);
+        let mut signed = file[..file.len() - TAG_LEN].to_vec();
+        signed[16..24].copy_from_slice(&modified.to_le_bytes());
+        Container::decode(&key.sign(&signed), &key, "one").unwrap()
+    }
+
+    /// Imports an archive holding `save` into `root`.
+    fn import(root: &TempDir, save: Container, conflict: Conflict) -> Imported {
+        let file = Archive::new(vec![save], 1_500_000_000)
+            .encode(PASSPHRASE)
+            .unwrap();
+        let archive = Archive::decode(&file, PASSPHRASE).unwrap();
+        let mut imported = archive.import(root.path(), &secret(), 5, conflict);
+        imported.pop().unwrap().1.unwrap()
+    }
+
+    fn secret() -> Secret {
+        Secret::new([7; 32])
+    }
+
+    /// Root of a computer whose slot `one` of `map` holds `payload`, saved at `modified`.
+    fn computer(payload: &[u8], modified: u64) -> TempDir {
+        let root = TempDir::new().unwrap();
+        Namespace::new(root.path(), "map", 5)
+            .write(&secret(), &save_at(payload, modified))
+            .unwrap();
+        root
+    }
+
+    fn read(root: &TempDir, slot: &str) -> Vec<u8> {
+        let namespace = Namespace::new(root.path(), "map", 5);
+        let save = namespace.read(&secret(), slot).unwrap().unwrap();
+        save.open(None).unwrap()
+    }
+
+    fn archive() -> Archive {
+        let saves = vec![
+            Container::seal("map", "one", b"first", None).unwrap(),
+            Container::seal("", "two", b"second", None).unwrap(),
+        ];
+        Archive::new(saves, 1_500_000_000)
+    }
+
+    #[test]
+    fn moves_saves_to_a_fresh_computer() {
+        // Saves of this user, found and exported the way `SCBank export` does.
+        let exporter = Secret::new([7; 32]);
+        let home = TempDir::new().unwrap();
+        let save = Container::seal("map", "one", b"first", None).unwrap();
+        Namespace::new(home.path(), "map", 0)
+            .write(&exporter, &save)
+            .unwrap();
+        let saves = find_saves(home.path(), &exporter)
+            .unwrap()
+            .into_iter()
+            .map(|(_, save)| save.unwrap())
+            .collect();
+        let file = Archive::new(saves, 1_500_000_000)
+            .encode(PASSPHRASE)
+            .unwrap();
+
+        // The other computer has a secret of its own.
+        let importer = Secret::new([8; 32]);
+        let root = TempDir::new().unwrap();
+        let archive = Archive::decode(&file, PASSPHRASE).unwrap();
+        for (_, imported) in archive.import(root.path(), &importer, 0, Conflict::KeepNewer) {
+            assert_eq!(imported.unwrap(), Imported::Added);
+        }
+        let namespace = Namespace::new(root.path(), "map", 0);
+        let save = namespace.read(&importer, "one").unwrap().unwrap();
+        assert_eq!(save.open(None).unwrap(), b"first");
+        // Signed again for the importer, the save no longer verifies with the exporter's secret.
+        assert!(namespace.read(&exporter, "one").is_err());
+    }
+
+    #[test]
+    fn refuses_a_wrong_passphrase() {
+        let file = archive().encode(PASSPHRASE).unwrap();
+        let e = Archive::decode(&file, "correct horse").err().unwrap();
+        assert!(matches!(
+            e.downcast_ref(),
+            Some(SaveErrorKind::WrongPassphrase)
+        ));
+    }
+
+    #[test]
+    fn refuses_edited_archives() {
+        let mut file = archive().encode(PASSPHRASE).unwrap();
+        let at = file.len() / 2;
+        file[at] ^= 1;
+        assert!(Archive::decode(&file, PASSPHRASE).is_err());
+    }
+
+    #[test]
+    fn keeps_the_newer_save() {
+        let root = computer(b"existing", 2_000);
+        assert_eq!(
+            import(&root, save_at(b"older", 1_000), Conflict::KeepNewer),
+            Imported::KeptExisting
+        );
+        assert_eq!(read(&root, "one"), b"existing");
+
+        assert_eq!(
+            import(&root, save_at(b"newer", 3_000), Conflict::KeepNewer),
+            Imported::Replaced
+        );
+        assert_eq!(read(&root, "one"), b"newer");
+        let namespace = Namespace::new(root.path(), "map", 5);
+        assert_eq!(namespace.backups_of("one").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn keeps_both_saves_in_numbered_slots() {
+        let root = computer(b"existing", 2_000);
+        assert_eq!(
+            import(&root, save_at(b"first", 1_000), Conflict::KeepBoth),
+            Imported::Renamed("one - imported".into())
+        );
+        assert_eq!(
+            import(&root, save_at(b"second", 3_000), Conflict::KeepBoth),
+            Imported::Renamed("one - imported 2".into())
+        );
+        assert_eq!(
+            import(&root, save_at(b"third", 3_000), Conflict::KeepBoth),
+            Imported::Renamed("one - imported 3".into())
+        );
+        assert_eq!(read(&root, "one"), b"existing");
+        assert_eq!(read(&root, "one - imported"), b"first");
+        assert_eq!(read(&root, "one - imported 2"), b"second");
+        assert_eq!(read(&root, "one - imported 3"), b"third");
+
+        // Encrypted saves are bound to their slot, so they can't be put next to it.
+        let encrypted =
+            Container::seal("map", "one", b"sealed", Some(&MapKey::new([3; 32]))).unwrap();
+        assert_eq!(
+            import(&root, encrypted, Conflict::KeepBoth),
+            Imported::KeptEncrypted
+        );
+        assert_eq!(read(&root, "one"), b"existing");
+    }
+
+    #[test]
+    fn overwrites_even_newer_saves() {
+        let root = computer(b"existing", 2_000);
+        assert_eq!(
+            import(&root, save_at(b"older", 1_000), Conflict::Overwrite),
+            Imported::Replaced
+        );
+        assert_eq!(read(&root, "one"), b"older");
+        // The replaced save is kept as a backup.
+        let namespace = Namespace::new(root.path(), "map", 5);
+        let backups = namespace.backups_of("one").unwrap();
+        assert_eq!(backups.len(), 1);
+        namespace.restore(&secret(), &backups[0]).unwrap();
+        assert_eq!(read(&root, "one"), b"existing");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reports_unreadable_folders() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = computer(b"existing", 2_000);
+        let locked = root.path().join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        if fs::read_dir(&locked).is_ok() {
+            // Running as root, which reads any folder.
+            return;
+        }
+        let found = find_saves(root.path(), &secret()).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(found.len(), 2);
+        assert!(found
+            .iter()
+            .any(|(path, save)| path == &locked && save.is_err()));
+        assert!(found.iter().any(|(_, save)| save.is_ok()));
+    }
+}
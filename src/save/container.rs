@@ -0,0 +1,400 @@
+//! The on-disk save format.
+//!
+//! ```text
+//! offset  size  field
+//! 0       4     magic, "SCBK"
+//! 4       2     format version
+//! 6       2     flags, see `FLAG_COMPRESSED` and `FLAG_ENCRYPTED`
+//! 8       8     created, seconds since the Unix epoch
+//! 16      8     modified, seconds since the Unix epoch
+//! 24      1+n   map identity, length prefixed UTF-8
+//! ..      1+n   slot name, length prefixed UTF-8
+//! ..      4+n   body, length prefixed
+//! ..      32    HMAC-SHA256 of everything before
+//! ```
+//!
+//! All integers are little endian.
+
+use std::{
+    io::{Read, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use getset::Getters;
+
+use super::{MapKey, SaveErrorKind, SaveKey, TAG_LEN};
+use crate::mem_lib;
+
+pub const MAGIC: &[u8; 4] = b"SCBK";
+pub const FORMAT_VERSION: u16 = 1;
+
+/// The body is zlib compressed, before encryption.
+pub const FLAG_COMPRESSED: u16 = 1;
+/// The body is encrypted with a map key.
+pub const FLAG_ENCRYPTED: u16 = 2;
+
+/// Payloads smaller than this aren't worth compressing.
+const COMPRESS_THRESHOLD: usize = 256;
+
+#[derive(Getters, Clone, Debug, PartialEq)]
+pub struct Container {
+    /// Identifies the map that wrote the save; empty if unknown.
+    #[get = "pub"]
+    map: String,
+    #[get = "pub"]
+    slot: String,
+    #[get = "pub"]
+    created: u64,
+    #[get = "pub"]
+    modified: u64,
+    #[get = "pub"]
+    flags: u16,
+    /// The payload after compression and encryption.
+    #[get = "pub"]
+    body: Vec<u8>,
+}
+
+impl Container {
+    /// Packs `payload`, compressing it when that helps and encrypting it if a map key is given.
+    pub fn seal(
+        map: &str,
+        slot: &str,
+        payload: &[u8],
+        map_key: Option<&MapKey>,
+    ) -> mem_lib::Result<Self> {
+        let mut flags = 0;
+        let mut body = payload.to_vec();
+        if payload.len() >= COMPRESS_THRESHOLD {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(payload)?;
+            let compressed = encoder.finish()?;
+            if compressed.len() < payload.len() {
+                body = compressed;
+                flags |= FLAG_COMPRESSED;
+            }
+        }
+        if let Some(map_key) = map_key {
+            body = map_key.encrypt(slot, &body)?;
+            flags |= FLAG_ENCRYPTED;
+        }
+        let now = now();
+        Ok(Container {
+            map: map.into(),
+            slot: slot.into(),
+            created: now,
+            modified: now,
+            flags,
+            body,
+        })
+    }
+
+    /// Unpacks the payload. `map_key` is required for encrypted saves and ignored otherwise.
+    pub fn open(&self, map_key: Option<&MapKey>) -> mem_lib::Result<Vec<u8>> {
+        let body = if self.is_encrypted() {
+            match map_key {
+                Some(map_key) => map_key.decrypt(&self.slot, &self.body)?,
+                None => return Err(SaveErrorKind::Decrypt.into()),
+            }
+        } else {
+            self.body.clone()
+        };
+        if !self.is_compressed() {
+            return Ok(body);
+        }
+        let mut payload = Vec::new();
+        ZlibDecoder::new(&body[..])
+            .read_to_end(&mut payload)
+            .map_err(|_| SaveErrorKind::Corrupt)?;
+        Ok(payload)
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.flags & FLAG_COMPRESSED != 0
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.flags & FLAG_ENCRYPTED != 0
+    }
+
+    /// Carries the creation time over from the save this one replaces.
+    pub fn replaces(&mut self, previous: &Container) {
+        self.created = previous.created;
+    }
+
+    pub fn encode(&self, key: &SaveKey) -> Vec<u8> {
+        let mut file = Vec::with_capacity(self.body.len() + 128);
+        file.extend_from_slice(MAGIC);
+        file.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        file.extend_from_slice(&self.flags.to_le_bytes());
+        file.extend_from_slice(&self.created.to_le_bytes());
+        file.extend_from_slice(&self.modified.to_le_bytes());
+        put_str(&mut file, &self.map);
+        put_str(&mut file, &self.slot);
+        file.extend_from_slice(&(self.body.len() as u32).to_le_bytes());
+        file.extend_from_slice(&self.body);
+        key.sign(&file)
+    }
+
+    /// Reads a save file, checking its tag and that it was saved as `slot`.
+    pub fn decode(file: &[u8], key: &SaveKey, slot: &str) -> mem_lib::Result<Self> {
+        if file.len() < MAGIC.len() + 2 + TAG_LEN || &file[..MAGIC.len()] != MAGIC {
+            return Err(SaveErrorKind::Corrupt.into());
+        }
+        // Only after the tag, so a damaged version reads as damage rather than as a newer format.
+        let signed = key.verify(file)?;
+        check_version(file)?;
+        let container = Self::parse(signed)?;
+        if container.slot != slot {
+            return Err(SaveErrorKind::Corrupt.into());
+        }
+        Ok(container)
+    }
+
+    /// Reads a save file without checking its tag, to show what it holds where the secret it
+    /// was signed with isn't available. Nothing read this way is to be trusted.
+    pub fn decode_unverified(file: &[u8]) -> mem_lib::Result<Self> {
+        check_version(file)?;
+        if file.len() < MAGIC.len() + 2 + TAG_LEN {
+            return Err(SaveErrorKind::Corrupt.into());
+        }
+        Self::parse(&file[..file.len() - TAG_LEN])
+    }
+
+    /// Reads the fields of a save file without its tag.
+    fn parse(signed: &[u8]) -> mem_lib::Result<Self> {
+        let mut reader = Reader {
+            bytes: signed,
+            at: MAGIC.len() + 2,
+        };
+        let flags = reader.u16()?;
+        let created = reader.u64()?;
+        let modified = reader.u64()?;
+        let map = reader.string()?;
+        let slot = reader.string()?;
+        let len = reader.u32()? as usize;
+        let body = reader.take(len)?.to_vec();
+        if reader.at != signed.len() {
+            return Err(SaveErrorKind::Corrupt.into());
+        }
+        Ok(Container {
+            map,
+            slot,
+            created,
+            modified,
+            flags,
+            body,
+        })
+    }
+
+    /// Reads the map and slot of a file without checking its tag, to find the key it should be
+    /// verified with.
+    pub fn peek(file: &[u8]) -> mem_lib::Result<(String, String)> {
+        check_version(file)?;
+        let mut reader = Reader {
+            bytes: file,
+            at: MAGIC.len() + 2,
+        };
+        reader.take(2 + 8 + 8)?;
+        let map = reader.string()?;
+        let slot = reader.string()?;
+        Ok((map, slot))
+    }
+
+    /// The same save under another slot name, or `None` if it is encrypted: encrypted bodies
+    /// are bound to the name they were saved under.
+    pub fn renamed(&self, slot: &str) -> Option<Self> {
+        if self.is_encrypted() {
+            return None;
+        }
+        Some(Container {
+            slot: slot.into(),
+            ..self.clone()
+        })
+    }
+}
+
+/// Checks the magic and the format version.
+fn check_version(file: &[u8]) -> mem_lib::Result<()> {
+    if file.len() < MAGIC.len() + 2 || &file[..MAGIC.len()] != MAGIC {
+        return Err(SaveErrorKind::Corrupt.into());
+    }
+    match u16::from_le_bytes([file[4], file[5]]) {
+        FORMAT_VERSION => Ok(()),
+        version if version > FORMAT_VERSION => {
+            Err(SaveErrorKind::UnsupportedFormat(version).into())
+        }
+        _ => Err(SaveErrorKind::Corrupt.into()),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// Writes `s` with a one byte length, cut to 255 bytes on a character boundary.
+fn put_str(file: &mut Vec<u8>, s: &str) {
+    let mut len = s.len().min(u8::MAX as usize);
+    while !s.is_char_boundary(len) {
+        len -= 1;
+    }
+    file.push(len as u8);
+    file.extend_from_slice(&s.as_bytes()[..len]);
+}
+
+/// Reads fields in order, failing with `SaveErrorKind::Corrupt` past the end.
+pub(super) struct Reader<'a> {
+    pub(super) bytes: &'a [u8],
+    pub(super) at: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(super) fn take(&mut self, len: usize) -> mem_lib::Result<&'a [u8]> {
+        let end = self
+            .at
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(SaveErrorKind::Corrupt)?;
+        let bytes = &self.bytes[self.at..end];
+        self.at = end;
+        Ok(bytes)
+    }
+
+    pub(super) fn u16(&mut self) -> mem_lib::Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub(super) fn u32(&mut self) -> mem_lib::Result<u32> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub(super) fn u64(&mut self) -> mem_lib::Result<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub(super) fn string(&mut self) -> mem_lib::Result<String> {
+        let len = self.take(1)?[0] as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| SaveErrorKind::Corrupt.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Container;
+    use crate::{
+        mem_lib,
+        save::{MapKey, SaveErrorKind, Secret},
+    };
+
+    fn is_damaged<T>(result: mem_lib::Result<T>) -> bool {
+        match result {
+            Ok(_) => false,
+            Err(e) => matches!(
+                e.downcast_ref(),
+                Some(SaveErrorKind::Corrupt) | Some(SaveErrorKind::BadSignature)
+            ),
+        }
+    }
+
+    fn saves() -> Vec<(Vec<u8>, Option<MapKey>)> {
+        let compressible = vec![0x5A; 4096];
+        vec![
+            (b"payload".to_vec(), None),
+            (compressible.clone(), None),
+            (b"payload".to_vec(), Some(MapKey::new([3; 32]))),
+            (compressible, Some(MapKey::new([3; 32]))),
+        ]
+    }
+
+    #[test]
+    fn round_trips() {
+        let key = Secret::new([7; 32]).key("map", "slot");
+        for (payload, map_key) in saves() {
+            let save = Container::seal("map", "slot", &payload, map_key.as_ref()).unwrap();
+            let decoded = Container::decode(&save.encode(&key), &key, "slot").unwrap();
+            assert_eq!(decoded, save);
+            assert_eq!(decoded.open(map_key.as_ref()).unwrap(), payload);
+        }
+    }
+
+    #[test]
+    fn compresses_large_payloads() {
+        let save = Container::seal("map", "slot", &[0x5A; 4096], None).unwrap();
+        assert!(save.is_compressed());
+        assert!(save.body().len() < 4096);
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let key = Secret::new([7; 32]).key("map", "slot");
+        for (payload, map_key) in saves() {
+            let save = Container::seal("map", "slot", &payload, map_key.as_ref()).unwrap();
+            let file = save.encode(&key);
+            for len in 0..file.len() {
+                assert!(
+                    is_damaged(Container::decode(&file[..len], &key, "slot")),
+                    "cut at {}",
+                    len
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_flipped_bits() {
+        let key = Secret::new([7; 32]).key("map", "slot");
+        for (payload, map_key) in saves() {
+            let save = Container::seal("map", "slot", &payload, map_key.as_ref()).unwrap();
+            let file = save.encode(&key);
+            for bit in 0..file.len() * 8 {
+                let mut flipped = file.clone();
+                flipped[bit / 8] ^= 1 << (bit % 8);
+                assert!(
+                    is_damaged(Container::decode(&flipped, &key, "slot")),
+                    "flipped bit {}",
+                    bit
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn peeks_at_map_and_slot() {
+        let key = Secret::new([7; 32]).key("map", "slot");
+        let file = Container::seal("map", "slot", b"payload", None)
+            .unwrap()
+            .encode(&key);
+        assert_eq!(
+            Container::peek(&file).unwrap(),
+            ("map".to_string(), "slot".to_string())
+        );
+        assert!(is_damaged(Container::peek(b"SC")));
+    }
+
+    #[test]
+    fn reads_unverified_saves_of_any_key() {
+        let save = Container::seal("map", "slot", b"payload", None).unwrap();
+        let file = save.encode(&Secret::new([7; 32]).key("map", "slot"));
+        assert_eq!(Container::decode_unverified(&file).unwrap(), save);
+        assert!(is_damaged(Container::decode_unverified(&file[..20])));
+    }
+
+    #[test]
+    fn opens_only_with_the_map_key() {
+        let save = Container::seal("map", "slot", b"payload", Some(&MapKey::new([3; 32]))).unwrap();
+        assert!(save.is_encrypted());
+        for map_key in &[None, Some(MapKey::new([4; 32]))] {
+            let e = save.open(map_key.as_ref()).unwrap_err();
+            assert!(matches!(e.downcast_ref(), Some(SaveErrorKind::Decrypt)));
+        }
+    }
+}
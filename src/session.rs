@@ -0,0 +1,626 @@
+use std::path::PathBuf;
+
+use getset::{Getters, MutGetters};
+
+use crate::{
+    config::Config,
+    mem_lib::{self, GameProcess, MemoryBackend, Module},
+    offsets::{OffsetTable, Offsets},
+    pe,
+    protocol::{self, MAGIC},
+    save::{self, Backup, Namespace, Secret, SlotInfo},
+    transfer::{self, Outcome, Streams},
+};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SCState {
+    FindingProcess,
+    FindingModule,
+    UnsupportedVersion,
+    FindingSCBankMap,
+    /// Waiting for the map to save to or load from a slot.
+    RequestSlot,
+    /// A chunked transfer is in progress; polled every frame.
+    Transferring,
+    Saved,
+    Loaded,
+    NoSaveFile,
+    TransferFailed,
+}
+
+impl SCState {
+    /// Whether the session found a StarCraft it supports.
+    pub fn is_attached(&self) -> bool {
+        !matches!(
+            self,
+            SCState::FindingProcess | SCState::FindingModule | SCState::UnsupportedVersion
+        )
+    }
+}
+
+/// Attaches to StarCraft and talks to the SCBank map, independent of any UI.
+#[derive(Getters, MutGetters)]
+pub struct Session<B: MemoryBackend> {
+    #[get = "pub"]
+    #[get_mut = "pub"]
+    state: SCState,
+    wait: u8,
+    #[get = "pub"]
+    process: B,
+    #[get = "pub"]
+    module: Module,
+    offset_table: OffsetTable,
+    /// File version of the attached `StarCraft.exe`.
+    #[get = "pub"]
+    game_version: Option<String>,
+    /// Offsets of the attached build, `None` unless it is a known one.
+    #[get = "pub"]
+    offsets: Option<Offsets>,
+    /// Where the saves of the running map go, known once it is found.
+    #[get = "pub"]
+    namespace: Option<Namespace>,
+    /// Saves of the running map, most recently played first.
+    #[get = "pub"]
+    slots: Vec<SlotInfo>,
+    /// Slot of the last finished request.
+    #[get = "pub"]
+    last_slot: Option<String>,
+    /// Folder holding the save folders of every map.
+    root: PathBuf,
+    /// Signs the save files written for the map.
+    secret: Secret,
+    config: Config,
+    streams: Streams,
+}
+
+impl Session<GameProcess> {
+    /// A session on this machine, with the offsets, secret and settings found on disk.
+    ///
+    /// Also removes what interrupted save writes left behind.
+    pub fn open() -> mem_lib::Result<Self> {
+        let secret = Secret::load_or_create()?;
+        let root = save::root()?;
+        match save::clean_temp_files(&root) {
+            Ok(0) => (),
+            Ok(removed) => eprintln!("Removed {} unfinished save file(s).", removed),
+            Err(e) => eprintln!("Couldn't look for unfinished save files: {}", e),
+        }
+        Ok(Session::new(
+            GameProcess::current_process(),
+            OffsetTable::load(),
+            secret,
+            Config::load(),
+            root,
+        ))
+    }
+}
+
+impl<B: MemoryBackend> Session<B> {
+    /// A session keeping the saves of every map under `root`.
+    pub fn new(
+        process: B,
+        offset_table: OffsetTable,
+        secret: Secret,
+        config: Config,
+        root: PathBuf,
+    ) -> Self {
+        Session {
+            state: SCState::FindingProcess,
+            wait: 0,
+            process,
+            module: Module::default(),
+            offset_table,
+            game_version: None,
+            offsets: None,
+            namespace: None,
+            slots: Vec::new(),
+            last_slot: None,
+            root,
+            secret,
+            config,
+            streams: Streams::default(),
+        }
+    }
+
+    /// Advances the state machine by one frame.
+    pub fn update(&mut self) {
+        if self.wait > 0 {
+            self.wait -= 1;
+            return;
+        }
+        self.state = match self.state {
+            SCState::FindingProcess => self.get_sc_proc(),
+            SCState::FindingModule | SCState::UnsupportedVersion => self.get_sc_module(),
+            SCState::FindingSCBankMap => self.check_scbank_map(),
+            SCState::RequestSlot | SCState::Transferring => self.serve_request(),
+            SCState::Saved | SCState::Loaded | SCState::NoSaveFile => SCState::RequestSlot,
+            SCState::TransferFailed => SCState::FindingSCBankMap,
+        };
+    }
+
+    pub fn get_sc_proc(&mut self) -> SCState {
+        match self.process.find_process("StarCraft.exe") {
+            Ok(()) => SCState::FindingModule,
+            Err(_) => {
+                self.wait = 127;
+                SCState::FindingProcess
+            }
+        }
+    }
+
+    pub fn get_sc_module(&mut self) -> SCState {
+        self.module = match self.process.get_module("StarCraft.exe") {
+            Ok(module) => module,
+            Err(_) => {
+                return SCState::FindingProcess;
+            }
+        };
+        self.game_version = pe::file_version(&self.module, &self.process).ok();
+        let offsets = match self.game_version {
+            Some(ref version) => self.offset_table.select(version),
+            None => None,
+        };
+        self.offsets = offsets.and_then(|offsets| {
+            offsets
+                .resolve(&self.module, &self.process)
+                .map_err(|e| eprintln!("Couldn't locate SCBank offsets: {}", e))
+                .ok()
+        });
+        if self.offsets.is_none() {
+            self.wait = 127;
+            return SCState::UnsupportedVersion;
+        }
+        SCState::FindingSCBankMap
+    }
+
+    pub fn check_scbank_map(&mut self) -> SCState {
+        let offsets = match self.offsets {
+            Some(ref offsets) => offsets,
+            None => return SCState::FindingModule,
+        };
+        if self
+            .module
+            .write::<u32>(&self.process, offsets.flag_ptr, 0)
+            .is_err()
+        {
+            return SCState::FindingProcess;
+        }
+        match self
+            .module
+            .read::<u32>(offsets.buffer_ptr + protocol::HEADER_OFFSET, &self.process)
+        {
+            Ok(MAGIC) => (),
+            Ok(_) => return SCState::FindingSCBankMap,
+            Err(_) => return SCState::FindingProcess,
+        }
+        let map = match transfer::read_map_id(&self.module, &self.process, offsets.buffer_ptr) {
+            Ok(map) => map,
+            Err(_) => return SCState::FindingProcess,
+        };
+        self.namespace = Some(Namespace::new(&self.root, &map, self.config.backups));
+        self.refresh_slots();
+        SCState::RequestSlot
+    }
+
+    /// Serves save/load requests while the SCBank map is running.
+    pub fn serve_request(&mut self) -> SCState {
+        let offsets = match self.offsets {
+            Some(ref offsets) => offsets,
+            None => return SCState::FindingModule,
+        };
+        let namespace = match self.namespace {
+            Some(ref namespace) => namespace,
+            None => return SCState::FindingSCBankMap,
+        };
+        match self
+            .module
+            .read::<u32>(offsets.buffer_ptr + protocol::HEADER_OFFSET, &self.process)
+        {
+            Ok(MAGIC) => (),
+            Ok(_) => {
+                self.streams = Streams::default();
+                return SCState::FindingSCBankMap;
+            }
+            Err(_) => {
+                self.streams = Streams::default();
+                return SCState::FindingProcess;
+            }
+        }
+        // Another map may have started without the magic going away in between; it must not
+        // get the saves of this one.
+        match transfer::read_map_id(&self.module, &self.process, offsets.buffer_ptr) {
+            Ok(ref map) if map == namespace.map() => (),
+            Ok(_) => {
+                self.streams = Streams::default();
+                return SCState::FindingSCBankMap;
+            }
+            Err(_) => {
+                self.streams = Streams::default();
+                return SCState::FindingProcess;
+            }
+        }
+
+        let outcome = transfer::poll(
+            &mut self.module,
+            &self.process,
+            offsets,
+            namespace,
+            &self.secret,
+            &mut self.streams,
+        );
+        let state = match outcome {
+            Ok(Outcome::Idle) if self.streams.is_active() => return SCState::Transferring,
+            Ok(Outcome::Idle) => return SCState::RequestSlot,
+            Ok(Outcome::Partial(_)) => return SCState::Transferring,
+            Ok(Outcome::Saved(slot)) => {
+                self.last_slot = Some(slot);
+                self.refresh_slots();
+                SCState::Saved
+            }
+            Ok(Outcome::Loaded(slot)) => {
+                self.last_slot = Some(slot);
+                SCState::Loaded
+            }
+            Ok(Outcome::NotFound(slot)) => {
+                self.last_slot = Some(slot);
+                SCState::NoSaveFile
+            }
+            Err(e) => {
+                eprintln!("SCBank request failed: {}", e);
+                SCState::TransferFailed
+            }
+        };
+        self.wait = 63;
+        state
+    }
+
+    /// Rereads the slot list of the running map.
+    pub fn refresh_slots(&mut self) {
+        self.slots = match self.namespace {
+            Some(ref namespace) => namespace.slots(&self.secret).unwrap_or_else(|e| {
+                eprintln!("Couldn't list the saves: {}", e);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+    }
+
+    /// Lists the backups of a slot of the running map, newest first.
+    pub fn backups_of(&self, slot: &str) -> Vec<Backup> {
+        match self.namespace {
+            Some(ref namespace) => namespace.backups_of(slot).unwrap_or_else(|e| {
+                eprintln!("Couldn't list the backups: {}", e);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn restore(&mut self, backup: &Backup) -> mem_lib::Result<()> {
+        if let Some(ref namespace) = self.namespace {
+            namespace.restore(&self.secret, backup)?;
+        }
+        self.refresh_slots();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::{SCState, Session};
+    use crate::{
+        config::Config,
+        mem_lib::{fake::FakeProcess, MemoryBackend},
+        offsets::{OffsetTable, Offsets},
+        protocol::{self, Command, Header, Status},
+        save::Secret,
+    };
+
+    /// Where the fake map keeps its payload window.
+    const WINDOW: u32 = 0x10_0000;
+
+    fn offsets() -> Offsets {
+        OffsetTable::embedded().builds()[0].clone()
+    }
+
+    fn session(process: &FakeProcess, root: &TempDir) -> Session<FakeProcess> {
+        Session::new(
+            process.clone(),
+            OffsetTable::embedded(),
+            Secret::new([7; 32]),
+            Config::default(),
+            root.path().to_path_buf(),
+        )
+    }
+
+    fn game() -> FakeProcess {
+        let process = FakeProcess::scbank_map(&offsets());
+        process.poke_bytes(
+            offsets().buffer_ptr + protocol::MAP_ID_OFFSET,
+            b"test-map\0",
+        );
+        process
+    }
+
+    /// Updates the session until it is in `state`, returning every state it went through.
+    fn run_until(session: &mut Session<FakeProcess>, state: SCState) -> Vec<SCState> {
+        let mut states = vec![*session.state()];
+        for _ in 0..1000 {
+            if *session.state() == state {
+                return states;
+            }
+            session.update();
+            if states.last() != Some(session.state()) {
+                states.push(*session.state());
+            }
+        }
+        panic!("Stuck in {:?} waiting for {:?}", session.state(), state);
+    }
+
+    fn header(process: &FakeProcess) -> Header {
+        let mut bytes = [0; protocol::HEADER_LEN];
+        process.peek_bytes(offsets().buffer_ptr + protocol::HEADER_OFFSET, &mut bytes);
+        Header::decode(&bytes)
+    }
+
+    /// Makes the map send a `command` request for the slot `name` with `chunk` in its window,
+    /// keeping the other fields of `header`.
+    fn send(process: &FakeProcess, name: &str, command: Command, chunk: &[u8], header: Header) {
+        let buffer = offsets().buffer_ptr;
+        process.poke_bytes(
+            buffer + protocol::NAME_OFFSET,
+            &protocol::encode_name(name).unwrap(),
+        );
+        process.poke_bytes(WINDOW, chunk);
+        let header = Header {
+            command,
+            sequence: header.sequence + 1,
+            payload_len: chunk.len() as u32,
+            checksum: protocol::adler32(chunk),
+            payload_ptr: WINDOW,
+            status: Status::Pending,
+            ..header
+        };
+        process.poke_bytes(buffer + protocol::HEADER_OFFSET, &header.encode());
+    }
+
+    #[test]
+    fn attaches_once_the_game_starts() {
+        let root = TempDir::new().unwrap();
+        let process = game();
+        process.kill();
+        let mut session = session(&process, &root);
+        for _ in 0..300 {
+            session.update();
+        }
+        assert_eq!(*session.state(), SCState::FindingProcess);
+        assert!(!session.state().is_attached());
+
+        process.restart();
+        assert_eq!(
+            run_until(&mut session, SCState::RequestSlot),
+            vec![
+                SCState::FindingProcess,
+                SCState::FindingModule,
+                SCState::FindingSCBankMap,
+                SCState::RequestSlot,
+            ]
+        );
+        assert_eq!(session.game_version().as_ref().unwrap(), "1.22.4.5993");
+        assert_eq!(session.namespace().as_ref().unwrap().map(), "test-map");
+        // The map is told SCBank is there.
+        assert_eq!(process.peek(offsets().flag_ptr), 0);
+    }
+
+    #[test]
+    fn waits_on_unknown_builds() {
+        let root = TempDir::new().unwrap();
+        let process = game();
+        process.set_file_version("1.16.1.1");
+        let mut session = session(&process, &root);
+        run_until(&mut session, SCState::UnsupportedVersion);
+        for _ in 0..300 {
+            session.update();
+            assert!(!session.state().is_attached());
+        }
+        // Nothing was written to the memory of the unknown build.
+        assert_eq!(process.peek(offsets().flag_ptr), 1);
+    }
+
+    #[test]
+    fn waits_for_the_map() {
+        let root = TempDir::new().unwrap();
+        let process = game();
+        let magic = offsets().buffer_ptr + protocol::HEADER_OFFSET;
+        process.poke(magic, 0);
+        let mut session = session(&process, &root);
+        run_until(&mut session, SCState::FindingSCBankMap);
+        for _ in 0..300 {
+            session.update();
+        }
+        assert_eq!(*session.state(), SCState::FindingSCBankMap);
+
+        process.poke(magic, protocol::MAGIC);
+        run_until(&mut session, SCState::RequestSlot);
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let root = TempDir::new().unwrap();
+        let process = game();
+        let mut session = session(&process, &root);
+        run_until(&mut session, SCState::RequestSlot);
+
+        send(&process, "slot", Command::Save, b"hello", header(&process));
+        session.update();
+        assert_eq!(*session.state(), SCState::Saved);
+        assert_eq!(session.last_slot().as_ref().unwrap(), "slot");
+        assert_eq!(session.slots().len(), 1);
+        let reply = header(&process);
+        assert_eq!(reply.status, Status::Done);
+        assert_eq!(reply.command, Command::Idle);
+        assert_eq!(reply.ack, reply.sequence);
+        run_until(&mut session, SCState::RequestSlot);
+
+        let window = vec![0; 16];
+        send(&process, "slot", Command::Load, &window, header(&process));
+        session.update();
+        assert_eq!(*session.state(), SCState::Loaded);
+        let reply = header(&process);
+        assert_eq!(reply.status, Status::Done);
+        assert_eq!(reply.payload_len, 5);
+        let mut loaded = [0; 5];
+        process.peek_bytes(WINDOW, &mut loaded);
+        assert_eq!(&loaded, b"hello");
+        run_until(&mut session, SCState::RequestSlot);
+
+        send(&process, "other", Command::Load, &window, header(&process));
+        session.update();
+        assert_eq!(*session.state(), SCState::NoSaveFile);
+        assert_eq!(header(&process).status, Status::NotFound);
+    }
+
+    #[test]
+    fn lists_numbered_slots() {
+        let root = TempDir::new().unwrap();
+        let process = game();
+        let mut session = session(&process, &root);
+        run_until(&mut session, SCState::RequestSlot);
+
+        let slot_ptr = offsets().buffer_ptr + protocol::SLOT_OFFSET;
+        process.poke_bytes(slot_ptr, &2u16.to_le_bytes());
+        send(&process, "hero", Command::Save, b"second", header(&process));
+        session.update();
+        assert_eq!(*session.state(), SCState::Saved);
+        assert_eq!(session.last_slot().as_ref().unwrap(), "Slot 2");
+        run_until(&mut session, SCState::RequestSlot);
+
+        process.poke_bytes(slot_ptr, &0u16.to_le_bytes());
+        send(&process, "hero", Command::Save, b"named", header(&process));
+        session.update();
+        assert_eq!(session.last_slot().as_ref().unwrap(), "hero");
+
+        let mut slots: Vec<_> = session
+            .slots()
+            .iter()
+            .map(|slot| slot.slot().clone())
+            .collect();
+        slots.sort();
+        assert_eq!(slots, ["Slot 2", "hero"]);
+    }
+
+    #[test]
+    fn follows_the_map_when_another_starts() {
+        let root = TempDir::new().unwrap();
+        let process = game();
+        let mut session = session(&process, &root);
+        run_until(&mut session, SCState::RequestSlot);
+        send(&process, "slot", Command::Save, b"hello", header(&process));
+        session.update();
+        assert_eq!(*session.state(), SCState::Saved);
+        run_until(&mut session, SCState::RequestSlot);
+
+        // The next map replaces the identifier without clearing the magic.
+        process.poke_bytes(
+            offsets().buffer_ptr + protocol::MAP_ID_OFFSET,
+            b"other-map\0",
+        );
+        send(&process, "slot", Command::Load, &[0; 16], header(&process));
+        session.update();
+        assert_eq!(*session.state(), SCState::FindingSCBankMap);
+        assert_eq!(header(&process).status, Status::Pending);
+
+        run_until(&mut session, SCState::RequestSlot);
+        assert_eq!(session.namespace().as_ref().unwrap().map(), "other-map");
+        assert!(session.slots().is_empty());
+        session.update();
+        assert_eq!(*session.state(), SCState::NoSaveFile);
+        assert_eq!(header(&process).status, Status::NotFound);
+    }
+
+    #[test]
+    fn reports_failed_transfers() {
+        let root = TempDir::new().unwrap();
+        let process = game();
+        let mut session = session(&process, &root);
+        run_until(&mut session, SCState::RequestSlot);
+
+        send(&process, "slot", Command::Save, b"hello", header(&process));
+        let checksum = offsets().buffer_ptr + protocol::HEADER_OFFSET + protocol::CHECKSUM_FIELD;
+        process.poke(checksum, 0);
+        session.update();
+        assert_eq!(*session.state(), SCState::TransferFailed);
+        assert_eq!(header(&process).status, Status::BadChecksum);
+        assert_eq!(
+            run_until(&mut session, SCState::RequestSlot),
+            vec![
+                SCState::TransferFailed,
+                SCState::FindingSCBankMap,
+                SCState::RequestSlot,
+            ]
+        );
+        assert!(session.slots().is_empty());
+    }
+
+    #[test]
+    fn survives_the_game_closing_mid_transfer() {
+        let root = TempDir::new().unwrap();
+        let process = game();
+        let mut session = session(&process, &root);
+        run_until(&mut session, SCState::RequestSlot);
+
+        let chunked = Header {
+            total_len: 10,
+            ..header(&process)
+        };
+        send(&process, "slot", Command::SaveChunk, b"01234", chunked);
+        session.update();
+        assert_eq!(*session.state(), SCState::Transferring);
+        assert_eq!(header(&process).status, Status::Partial);
+        assert_eq!(header(&process).offset, 5);
+
+        process.kill();
+        session.update();
+        assert_eq!(*session.state(), SCState::FindingProcess);
+        assert!(process.clone().find_process("StarCraft.exe").is_err());
+
+        process.restart();
+        run_until(&mut session, SCState::RequestSlot);
+        // The half received save is gone; the map is told to start over.
+        send(
+            &process,
+            "slot",
+            Command::SaveChunk,
+            b"56789",
+            header(&process),
+        );
+        session.update();
+        assert_eq!(*session.state(), SCState::TransferFailed);
+        let reply = header(&process);
+        assert_eq!(reply.status, Status::OutOfOrder);
+        assert_eq!(reply.offset, 0);
+        assert!(session.slots().is_empty());
+
+        run_until(&mut session, SCState::RequestSlot);
+        send(
+            &process,
+            "slot",
+            Command::SaveChunk,
+            b"01234",
+            header(&process),
+        );
+        session.update();
+        send(
+            &process,
+            "slot",
+            Command::SaveChunk,
+            b"56789",
+            header(&process),
+        );
+        session.update();
+        assert_eq!(*session.state(), SCState::Saved);
+        assert_eq!(session.slots().len(), 1);
+    }
+}
@@ -0,0 +1,666 @@
+//! Serves the save and load requests a map makes through the shared buffer, see `protocol`.
+
+use std::cmp;
+
+use failure::Fail;
+
+use crate::{
+    mem_lib::{self, MemoryBackend, Module},
+    offsets::Offsets,
+    protocol::{self, Command, Header, Status},
+    save::{self, Container, MapKey, Namespace, SaveErrorKind, Secret},
+};
+
+/// Largest payload window SCBank accepts from a map.
+pub const MAX_PAYLOAD_LEN: u32 = 0x10000;
+/// Largest save SCBank accepts through chunked transfers, not counting the signature.
+pub const MAX_SAVE_LEN: u32 = 0x100_0000;
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Idle,
+    Saved(String),
+    Loaded(String),
+    NotFound(String),
+    /// A chunk was transferred; the map continues at the given offset.
+    Partial(u32),
+}
+
+/// Chunked transfers in progress, kept between frames.
+#[derive(Default)]
+pub struct Streams {
+    save: Option<Stream>,
+    load: Option<Stream>,
+}
+
+struct Stream {
+    name: String,
+    data: Vec<u8>,
+}
+
+impl Streams {
+    pub fn is_active(&self) -> bool {
+        self.save.is_some() || self.load.is_some()
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum TransferErrorKind {
+    #[fail(display = "Invalid save file name: {:?}", _0)]
+    InvalidFilename(String),
+
+    #[fail(display = "Payload of {} bytes is too large", _0)]
+    PayloadTooLarge(u32),
+
+    #[fail(display = "Unknown command: {}", _0)]
+    UnknownCommand(u16),
+
+    #[fail(display = "Unsupported protocol version: {}", _0)]
+    UnsupportedProtocol(u16),
+
+    #[fail(display = "The map has no identifier")]
+    NoMapId,
+
+    #[fail(display = "Checksum mismatch: expected {:#X}, got {:#X}", _0, _1)]
+    BadChecksum(u32, u32),
+
+    #[fail(display = "Chunk out of order, expected offset {}", _0)]
+    OutOfOrder(u32),
+
+    #[fail(display = "Empty payload window")]
+    EmptyWindow,
+
+    #[fail(display = "Payload window {:#X}+{} is outside StarCraft", _0, _1)]
+    BadWindow(u32, u32),
+}
+
+impl TransferErrorKind {
+    fn status(&self) -> Status {
+        match self {
+            TransferErrorKind::UnsupportedProtocol(_) => Status::UnsupportedVersion,
+            TransferErrorKind::BadChecksum(..) => Status::BadChecksum,
+            _ => Status::Error,
+        }
+    }
+}
+
+pub fn read_header(
+    module: &Module,
+    process: &impl MemoryBackend,
+    buffer: u32,
+) -> mem_lib::Result<Header> {
+    let mut bytes = [0; protocol::HEADER_LEN];
+    module.read_into(buffer + protocol::HEADER_OFFSET, &mut bytes, process)?;
+    Ok(Header::decode(&bytes))
+}
+
+/// Serves the pending request of the map, if any.
+///
+/// On errors other than failing to access memory, the map is told through the status field.
+pub fn poll(
+    module: &mut Module,
+    process: &impl MemoryBackend,
+    offsets: &Offsets,
+    namespace: &Namespace,
+    secret: &Secret,
+    streams: &mut Streams,
+) -> mem_lib::Result<Outcome> {
+    let buffer = offsets.buffer_ptr;
+    let header = read_header(module, process, buffer)?;
+    if header.command == Command::Idle {
+        return Ok(Outcome::Idle);
+    }
+
+    let result = if header.version < protocol::MIN_PROTOCOL_VERSION
+        || header.version > protocol::PROTOCOL_VERSION
+    {
+        Err(TransferErrorKind::UnsupportedProtocol(header.version).into())
+    } else if namespace.map().is_empty() {
+        Err(TransferErrorKind::NoMapId.into())
+    } else if !is_in_module(module, header.payload_ptr, header.payload_len) {
+        Err(TransferErrorKind::BadWindow(header.payload_ptr, header.payload_len).into())
+    } else {
+        match header.command {
+            Command::Save => save(module, process, buffer, &header, namespace, secret),
+            Command::Load => load(module, process, buffer, &header, namespace, secret),
+            Command::SaveChunk => {
+                save_chunk(module, process, buffer, &header, namespace, secret, streams)
+            }
+            Command::LoadChunk => {
+                load_chunk(module, process, buffer, &header, namespace, secret, streams)
+            }
+            _ => Err(TransferErrorKind::UnknownCommand(header.command.into()).into()),
+        }
+    };
+    let (status, offset) = match result {
+        Ok(Outcome::NotFound(_)) => (Status::NotFound, None),
+        Ok(Outcome::Partial(next)) => (Status::Partial, Some(next)),
+        Ok(_) => (Status::Done, None),
+        Err(ref e) => match e.downcast_ref::<TransferErrorKind>() {
+            Some(TransferErrorKind::OutOfOrder(expected)) => (Status::OutOfOrder, Some(*expected)),
+            Some(kind) => (kind.status(), None),
+            None => match e.downcast_ref::<SaveErrorKind>() {
+                Some(SaveErrorKind::BadSignature) | Some(SaveErrorKind::Truncated(_)) => {
+                    (Status::BadSignature, None)
+                }
+                Some(SaveErrorKind::Decrypt) => (Status::BadKey, None),
+                _ => (Status::Error, None),
+            },
+        },
+    };
+    reply(module, process, buffer, &header, status, offset)?;
+    result
+}
+
+/// Publishes `status` and hands the buffer back to the map; `command` is written last.
+fn reply(
+    module: &mut Module,
+    process: &impl MemoryBackend,
+    buffer: u32,
+    header: &Header,
+    status: Status,
+    offset: Option<u32>,
+) -> mem_lib::Result<()> {
+    let header_ptr = buffer + protocol::HEADER_OFFSET;
+    if let Some(offset) = offset {
+        module.write::<u32>(process, header_ptr + protocol::OFFSET_FIELD, offset)?;
+    }
+    module.write::<u16>(process, header_ptr + protocol::STATUS_FIELD, status.into())?;
+    module.write::<u32>(process, header_ptr + protocol::ACK_FIELD, header.sequence)?;
+    module.write::<u16>(
+        process,
+        header_ptr + protocol::COMMAND_FIELD,
+        Command::Idle.into(),
+    )
+}
+
+fn save(
+    module: &Module,
+    process: &impl MemoryBackend,
+    buffer: u32,
+    header: &Header,
+    namespace: &Namespace,
+    secret: &Secret,
+) -> mem_lib::Result<Outcome> {
+    let name = read_slot(module, process, buffer, header)?;
+    if header.payload_len > MAX_PAYLOAD_LEN {
+        return Err(TransferErrorKind::PayloadTooLarge(header.payload_len).into());
+    }
+
+    let mut payload = vec![0; header.payload_len as usize];
+    module.read_into(header.payload_ptr, &mut payload, process)?;
+    let checksum = protocol::adler32(&payload);
+    if checksum != header.checksum {
+        return Err(TransferErrorKind::BadChecksum(header.checksum, checksum).into());
+    }
+    let map_key = read_map_key(module, process, buffer, header)?;
+    write_save(namespace, &name, &payload, secret, map_key.as_ref())?;
+    Ok(Outcome::Saved(name))
+}
+
+fn load(
+    module: &mut Module,
+    process: &impl MemoryBackend,
+    buffer: u32,
+    header: &Header,
+    namespace: &Namespace,
+    secret: &Secret,
+) -> mem_lib::Result<Outcome> {
+    let name = read_slot(module, process, buffer, header)?;
+    let map_key = read_map_key(module, process, buffer, header)?;
+    let payload = match read_save(namespace, &name, secret, map_key.as_ref())? {
+        Some(payload) => payload,
+        None => return Ok(Outcome::NotFound(name)),
+    };
+    if payload.len() > header.payload_len as usize {
+        return Err(TransferErrorKind::PayloadTooLarge(payload.len() as u32).into());
+    }
+
+    let header_ptr = buffer + protocol::HEADER_OFFSET;
+    module.write_from(header.payload_ptr, &payload, process)?;
+    module.write::<u32>(
+        process,
+        header_ptr + protocol::PAYLOAD_LEN_FIELD,
+        payload.len() as u32,
+    )?;
+    module.write::<u32>(
+        process,
+        header_ptr + protocol::CHECKSUM_FIELD,
+        protocol::adler32(&payload),
+    )?;
+    Ok(Outcome::Loaded(name))
+}
+
+/// Receives one chunk of a save larger than the payload window.
+///
+/// A chunk may start anywhere up to the end of what was received, so the map can resend
+/// chunks whose reply it missed.
+fn save_chunk(
+    module: &Module,
+    process: &impl MemoryBackend,
+    buffer: u32,
+    header: &Header,
+    namespace: &Namespace,
+    secret: &Secret,
+    streams: &mut Streams,
+) -> mem_lib::Result<Outcome> {
+    let name = read_slot(module, process, buffer, header)?;
+    if header.total_len > MAX_SAVE_LEN {
+        return Err(TransferErrorKind::PayloadTooLarge(header.total_len).into());
+    }
+    if header.payload_len > MAX_PAYLOAD_LEN {
+        return Err(TransferErrorKind::PayloadTooLarge(header.payload_len).into());
+    }
+
+    let mut chunk = vec![0; header.payload_len as usize];
+    module.read_into(header.payload_ptr, &mut chunk, process)?;
+    let checksum = protocol::adler32(&chunk);
+    if checksum != header.checksum {
+        return Err(TransferErrorKind::BadChecksum(header.checksum, checksum).into());
+    }
+
+    if header.offset == 0 {
+        streams.save = Some(Stream {
+            name: name.clone(),
+            data: Vec::with_capacity(header.total_len as usize),
+        });
+    }
+    let stream = match streams.save {
+        Some(ref mut stream) if stream.name == name => stream,
+        _ => return Err(TransferErrorKind::OutOfOrder(0).into()),
+    };
+    let received = stream.data.len() as u32;
+    if header.offset > received {
+        return Err(TransferErrorKind::OutOfOrder(received).into());
+    }
+    stream.data.truncate(header.offset as usize);
+    stream.data.extend_from_slice(&chunk);
+    let received = stream.data.len() as u32;
+    if received > header.total_len {
+        streams.save = None;
+        return Err(TransferErrorKind::PayloadTooLarge(received).into());
+    }
+    if received < header.total_len {
+        return Ok(Outcome::Partial(received));
+    }
+
+    let map_key = read_map_key(module, process, buffer, header)?;
+    write_save(namespace, &name, &stream.data, secret, map_key.as_ref())?;
+    streams.save = None;
+    Ok(Outcome::Saved(name))
+}
+
+/// Sends the chunk of a save starting at `header.offset`, as much as the window holds.
+fn load_chunk(
+    module: &mut Module,
+    process: &impl MemoryBackend,
+    buffer: u32,
+    header: &Header,
+    namespace: &Namespace,
+    secret: &Secret,
+    streams: &mut Streams,
+) -> mem_lib::Result<Outcome> {
+    let name = read_slot(module, process, buffer, header)?;
+    if header.payload_len == 0 {
+        return Err(TransferErrorKind::EmptyWindow.into());
+    }
+
+    let cached = match streams.load {
+        Some(ref stream) => stream.name == name,
+        None => false,
+    };
+    if header.offset == 0 || !cached {
+        streams.load = None;
+        let map_key = read_map_key(module, process, buffer, header)?;
+        let data = match read_save(namespace, &name, secret, map_key.as_ref())? {
+            Some(data) => data,
+            None => return Ok(Outcome::NotFound(name)),
+        };
+        streams.load = Some(Stream {
+            name: name.clone(),
+            data,
+        });
+    }
+    let data = match streams.load {
+        Some(ref stream) => &stream.data,
+        None => return Err(TransferErrorKind::OutOfOrder(0).into()),
+    };
+    let start = header.offset as usize;
+    if start > data.len() {
+        return Err(TransferErrorKind::OutOfOrder(0).into());
+    }
+    let end = cmp::min(start + header.payload_len as usize, data.len());
+    let chunk = &data[start..end];
+
+    let header_ptr = buffer + protocol::HEADER_OFFSET;
+    module.write_from(header.payload_ptr, chunk, process)?;
+    module.write::<u32>(
+        process,
+        header_ptr + protocol::PAYLOAD_LEN_FIELD,
+        chunk.len() as u32,
+    )?;
+    module.write::<u32>(
+        process,
+        header_ptr + protocol::CHECKSUM_FIELD,
+        protocol::adler32(chunk),
+    )?;
+    module.write::<u32>(
+        process,
+        header_ptr + protocol::TOTAL_LEN_FIELD,
+        data.len() as u32,
+    )?;
+    if end < data.len() {
+        return Ok(Outcome::Partial(end as u32));
+    }
+    streams.load = None;
+    Ok(Outcome::Loaded(name))
+}
+
+/// Whether the `len` bytes at `ptr` from the base lie inside `module`. The map chooses both,
+/// so a buggy or hostile map could otherwise point SCBank anywhere in StarCraft.
+fn is_in_module(module: &Module, ptr: u32, len: u32) -> bool {
+    matches!(ptr.checked_add(len), Some(end) if end <= *module.size())
+}
+
+/// Reads the map key if the request asks for encryption.
+fn read_map_key(
+    module: &Module,
+    process: &impl MemoryBackend,
+    buffer: u32,
+    header: &Header,
+) -> mem_lib::Result<Option<MapKey>> {
+    if header.flags & protocol::FLAG_ENCRYPT == 0 {
+        return Ok(None);
+    }
+    let mut key = [0; protocol::MAP_KEY_LEN];
+    module.read_into(buffer + protocol::MAP_KEY_OFFSET, &mut key, process)?;
+    Ok(Some(MapKey::new(key)))
+}
+
+fn write_save(
+    namespace: &Namespace,
+    name: &str,
+    payload: &[u8],
+    secret: &Secret,
+    map_key: Option<&MapKey>,
+) -> mem_lib::Result<()> {
+    let mut container = Container::seal(namespace.map(), name, payload, map_key)?;
+    if let Ok(Some(previous)) = namespace.read(secret, name) {
+        container.replaces(&previous);
+    }
+    namespace.write(secret, &container)
+}
+
+/// Returns the payload of the save `name`, or `None` if there is no such save.
+fn read_save(
+    namespace: &Namespace,
+    name: &str,
+    secret: &Secret,
+    map_key: Option<&MapKey>,
+) -> mem_lib::Result<Option<Vec<u8>>> {
+    match namespace.read(secret, name)? {
+        Some(container) => Ok(Some(container.open(map_key)?)),
+        None => Ok(None),
+    }
+}
+
+/// Reads the slot a request is for: numbered if the map sets one, or else the save name.
+fn read_slot(
+    module: &Module,
+    process: &impl MemoryBackend,
+    buffer: u32,
+    header: &Header,
+) -> mem_lib::Result<String> {
+    if header.version >= 4 {
+        let number = module.read::<u16>(buffer + protocol::SLOT_OFFSET, process)?;
+        if number != 0 {
+            return Ok(save::slot_name(number));
+        }
+    }
+    let mut raw = [0; protocol::NAME_LEN];
+    module.read_into(buffer + protocol::NAME_OFFSET, &mut raw, process)?;
+    let name = protocol::decode_name(&raw);
+    if !save::is_valid_name(&name) {
+        return Err(TransferErrorKind::InvalidFilename(name).into());
+    }
+    Ok(name)
+}
+
+/// Reads the identifier a map publishes.
+pub fn read_map_id(
+    module: &Module,
+    process: &impl MemoryBackend,
+    buffer: u32,
+) -> mem_lib::Result<String> {
+    let mut raw = [0; protocol::MAP_ID_LEN];
+    module.read_into(buffer + protocol::MAP_ID_OFFSET, &mut raw, process)?;
+    Ok(protocol::decode_name(&raw))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::{poll, Streams};
+    use crate::{
+        mem_lib::{fake::FakeProcess, MemoryBackend},
+        offsets::{OffsetTable, Offsets},
+        protocol::{self, Command, Header, Status, FLAG_ENCRYPT},
+        save::{Namespace, Secret},
+    };
+
+    const WINDOW: u32 = 0x10_0000;
+
+    struct Map {
+        process: FakeProcess,
+        offsets: Offsets,
+        root: TempDir,
+        secret: Secret,
+        streams: Streams,
+        map_id: String,
+    }
+
+    impl Map {
+        fn new() -> Self {
+            let offsets = OffsetTable::embedded().builds()[0].clone();
+            let mut process = FakeProcess::scbank_map(&offsets);
+            process.find_process("StarCraft.exe").unwrap();
+            process.poke_bytes(
+                offsets.buffer_ptr + protocol::NAME_OFFSET,
+                &protocol::encode_name("slot").unwrap(),
+            );
+            Map {
+                process,
+                offsets,
+                root: TempDir::new().unwrap(),
+                secret: Secret::new([7; 32]),
+                streams: Streams::default(),
+                map_id: "test-map".into(),
+            }
+        }
+
+        /// Makes a request with `payload` in the window and serves it, returning the reply and
+        /// whether serving it succeeded.
+        fn request(&mut self, header: Header, payload: &[u8]) -> (Header, bool) {
+            let header_ptr = self.offsets.buffer_ptr + protocol::HEADER_OFFSET;
+            if header.payload_ptr == WINDOW {
+                self.process.poke_bytes(WINDOW, payload);
+            }
+            let header = Header {
+                checksum: protocol::adler32(payload),
+                ..header
+            };
+            self.process.poke_bytes(header_ptr, &header.encode());
+            let namespace = Namespace::new(self.root.path(), &self.map_id, 5);
+            let result = poll(
+                &mut self.process.module(),
+                &self.process,
+                &self.offsets,
+                &namespace,
+                &self.secret,
+                &mut self.streams,
+            );
+            let mut bytes = [0; protocol::HEADER_LEN];
+            self.process.peek_bytes(header_ptr, &mut bytes);
+            (Header::decode(&bytes), result.is_ok())
+        }
+    }
+
+    fn header(command: Command, payload_ptr: u32, payload_len: u32) -> Header {
+        Header {
+            command,
+            sequence: 1,
+            payload_ptr,
+            payload_len,
+            ..Header::default()
+        }
+    }
+
+    #[test]
+    fn saves_and_loads_in_chunks() {
+        let mut map = Map::new();
+        let save = Header {
+            total_len: 6,
+            ..header(Command::SaveChunk, WINDOW, 4)
+        };
+        let (reply, _) = map.request(save.clone(), b"abcd");
+        assert_eq!((reply.status, reply.offset), (Status::Partial, 4));
+        let (reply, _) = map.request(
+            Header {
+                offset: 4,
+                payload_len: 2,
+                ..save
+            },
+            b"ef",
+        );
+        assert_eq!(reply.status, Status::Done);
+
+        let load = header(Command::LoadChunk, WINDOW, 4);
+        let (reply, _) = map.request(load.clone(), &[0; 4]);
+        assert_eq!((reply.status, reply.total_len), (Status::Partial, 6));
+        let (reply, _) = map.request(Header { offset: 4, ..load }, &[0; 4]);
+        assert_eq!((reply.status, reply.payload_len), (Status::Done, 2));
+        let mut loaded = [0; 2];
+        map.process.peek_bytes(WINDOW, &mut loaded);
+        assert_eq!(&loaded, b"ef");
+    }
+
+    #[test]
+    fn encrypts_with_the_map_key() {
+        let mut map = Map::new();
+        let key_ptr = map.offsets.buffer_ptr + protocol::MAP_KEY_OFFSET;
+        let encrypted = |command, len| Header {
+            flags: FLAG_ENCRYPT,
+            ..header(command, WINDOW, len)
+        };
+        map.process.poke_bytes(key_ptr, &[1; protocol::MAP_KEY_LEN]);
+        let (reply, _) = map.request(encrypted(Command::Save, 6), b"secret");
+        assert_eq!(reply.status, Status::Done);
+
+        let (reply, _) = map.request(encrypted(Command::Load, 6), &[0; 6]);
+        assert_eq!(reply.status, Status::Done);
+        let mut loaded = [0; 6];
+        map.process.peek_bytes(WINDOW, &mut loaded);
+        assert_eq!(&loaded, b"secret");
+
+        // Without the flag, or with another key, the save can't be read.
+        let (reply, _) = map.request(header(Command::Load, WINDOW, 6), &[0; 6]);
+        assert_eq!(reply.status, Status::BadKey);
+        map.process.poke_bytes(key_ptr, &[2; protocol::MAP_KEY_LEN]);
+        let (reply, _) = map.request(encrypted(Command::Load, 6), &[0; 6]);
+        assert_eq!(reply.status, Status::BadKey);
+    }
+
+    #[test]
+    fn saves_to_numbered_slots() {
+        let mut map = Map::new();
+        let slot_ptr = map.offsets.buffer_ptr + protocol::SLOT_OFFSET;
+        map.process.poke_bytes(slot_ptr, &3u16.to_le_bytes());
+        let (reply, _) = map.request(header(Command::Save, WINDOW, 5), b"third");
+        assert_eq!(reply.status, Status::Done);
+        // Version 3 maps have no slot number; the name is used whatever is at the offset.
+        let old = Header {
+            version: 3,
+            ..header(Command::Save, WINDOW, 4)
+        };
+        let (reply, _) = map.request(old, b"name");
+        assert_eq!(reply.status, Status::Done);
+
+        let namespace = Namespace::new(map.root.path(), "test-map", 5);
+        let open = |slot| {
+            let container = namespace.read(&map.secret, slot).unwrap().unwrap();
+            container.open(None).unwrap()
+        };
+        assert_eq!(open("Slot 3"), b"third");
+        assert_eq!(open("slot"), b"name");
+
+        // A zero slot falls back to the save name.
+        map.process.poke_bytes(slot_ptr, &0u16.to_le_bytes());
+        let (reply, _) = map.request(header(Command::Load, WINDOW, 8), &[0; 8]);
+        assert_eq!((reply.status, reply.payload_len), (Status::Done, 4));
+        map.process.poke_bytes(slot_ptr, &3u16.to_le_bytes());
+        let (reply, _) = map.request(header(Command::Load, WINDOW, 8), &[0; 8]);
+        assert_eq!((reply.status, reply.payload_len), (Status::Done, 5));
+    }
+
+    #[test]
+    fn refuses_maps_without_an_identifier() {
+        let mut map = Map::new();
+        for version in 1..protocol::MIN_PROTOCOL_VERSION {
+            let old = Header {
+                version,
+                ..header(Command::Save, WINDOW, 5)
+            };
+            let (reply, ok) = map.request(old, b"hello");
+            assert!(!ok && reply.status == Status::UnsupportedVersion);
+        }
+        map.map_id.clear();
+        let (reply, ok) = map.request(header(Command::Save, WINDOW, 5), b"hello");
+        assert!(!ok && reply.status == Status::Error);
+        assert_eq!(std::fs::read_dir(map.root.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn rejects_windows_outside_the_module() {
+        let mut map = Map::new();
+        let (reply, ok) = map.request(header(Command::Save, WINDOW, 5), b"hello");
+        assert!(ok && reply.status == Status::Done);
+
+        let size = *map.process.module().size();
+        for &(ptr, len) in &[
+            (0xFFFF_FFF0, 0x20),
+            (size - 0x10, 0x20),
+            (size, 1),
+            (0, u32::MAX),
+        ] {
+            for &command in &[
+                Command::Save,
+                Command::Load,
+                Command::SaveChunk,
+                Command::LoadChunk,
+            ] {
+                let (reply, ok) = map.request(header(command, ptr, len), b"");
+                assert!(!ok);
+                assert_eq!(reply.status, Status::Error);
+                assert_eq!(reply.command, Command::Idle);
+            }
+        }
+        // The end of the module wasn't touched by the loads.
+        let mut end = [0; 0x10];
+        map.process.peek_bytes(size - 0x10, &mut end);
+        assert_eq!(end, [0; 0x10]);
+    }
+
+    #[test]
+    fn serves_windows_at_the_end_of_the_module() {
+        let mut map = Map::new();
+        let (_, ok) = map.request(header(Command::Save, WINDOW, 5), b"hello");
+        assert!(ok);
+        let size = *map.process.module().size();
+        let (reply, ok) = map.request(header(Command::Load, size - 5, 5), b"");
+        assert!(ok && reply.status == Status::Done);
+        let mut loaded = [0; 5];
+        map.process.peek_bytes(size - 5, &mut loaded);
+        assert_eq!(&loaded, b"hello");
+    }
+}
@@ -0,0 +1,473 @@
+//! Updating SCBank itself. The check and the download run on a worker thread that reports back
+//! over a channel, so the launcher keeps drawing meanwhile. Releases come from a
+//! `source::ReleaseSource`, GitHub unless `scbank.toml` names another. A newer release waits
+//! with its notes until the user chooses to install it.
+
+use std::{
+    cmp::Ordering,
+    env, fs,
+    io::{Read, Write},
+    path::Path,
+    sync::mpsc::{self, Receiver, Sender, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use failure::Fail;
+
+pub mod rollback;
+pub mod signature;
+pub mod source;
+
+use self::{
+    signature::SIGNATURE_LEN,
+    source::{Release, ReleaseSource},
+};
+use crate::config::Channel;
+
+/// How long the result of an update stays on screen.
+const RESULT_TIME: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Fail, Clone, PartialEq)]
+pub enum UpdateErrorKind {
+    #[fail(display = "Could not reach the update source: {}", _0)]
+    Network(String),
+
+    #[fail(display = "Invalid release manifest: {}", _0)]
+    BadManifest(String),
+
+    #[fail(display = "This build has no key to check updates with")]
+    NoPublicKey,
+
+    #[fail(display = "The update has no signature")]
+    Unsigned,
+
+    #[fail(display = "The signature of the update doesn't match")]
+    BadSignature,
+
+    #[fail(display = "The latest release has no build for {}", _0)]
+    NoBuild(String),
+
+    #[fail(display = "Could not install the update: {}", _0)]
+    Install(String),
+
+    #[fail(display = "The update check stopped unexpectedly")]
+    Interrupted,
+}
+
+/// A release newer than the running version.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Changes {
+    pub version: String,
+    pub notes: source::Notes,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UpdateState {
+    Checking,
+    /// The releases since this version, newest first, waiting for `Updater::install`. The
+    /// first is the one to install.
+    Available(Vec<Changes>),
+    /// Bytes downloaded so far, and in total if the server said.
+    Downloading {
+        done: u64,
+        total: Option<u64>,
+    },
+    Installing,
+    UpToDate,
+    /// Installed the version given; it runs from the next start.
+    Updated(String),
+    Failed(UpdateErrorKind),
+}
+
+impl UpdateState {
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            UpdateState::UpToDate | UpdateState::Updated(_) | UpdateState::Failed(_)
+        )
+    }
+
+    /// Share of the download done, from 0 to 1, if known.
+    pub fn progress(&self) -> Option<f32> {
+        match *self {
+            UpdateState::Downloading {
+                done,
+                total: Some(total),
+            } if total > 0 => Some((done as f32 / total as f32).min(1.0)),
+            UpdateState::Installing => Some(1.0),
+            _ => None,
+        }
+    }
+}
+
+/// An update running on a worker thread.
+pub struct Updater {
+    receiver: Receiver<UpdateState>,
+    install: Sender<()>,
+    state: UpdateState,
+    finished: Option<Instant>,
+}
+
+impl Updater {
+    /// Starts checking `source` for a newer release on `channel`. Dropping the updater while a
+    /// release is available declines it.
+    pub fn start(source: Box<dyn ReleaseSource>, channel: Channel) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let (install, confirmed) = mpsc::channel();
+        thread::spawn(move || {
+            let state = match update(&*source, channel, &sender, &confirmed) {
+                Ok(state) => state,
+                Err(e) => UpdateState::Failed(e),
+            };
+            let _ = sender.send(state);
+        });
+        Updater {
+            receiver,
+            install,
+            state: UpdateState::Checking,
+            finished: None,
+        }
+    }
+
+    /// Takes in what the worker reported since the last call.
+    pub fn poll(&mut self) -> &UpdateState {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(state) => self.state = state,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if !self.state.is_finished() {
+                        self.state = UpdateState::Failed(UpdateErrorKind::Interrupted);
+                    }
+                    break;
+                }
+            }
+        }
+        if self.state.is_finished() && self.finished.is_none() {
+            self.finished = Some(Instant::now());
+        }
+        &self.state
+    }
+
+    pub fn state(&self) -> &UpdateState {
+        &self.state
+    }
+
+    /// Installs the release offered by `UpdateState::Available`.
+    pub fn install(&self) {
+        if let UpdateState::Available(_) = self.state {
+            let _ = self.install.send(());
+        }
+    }
+
+    /// Whether the result has been shown long enough.
+    pub fn is_done(&self) -> bool {
+        self.finished
+            .is_some_and(|finished| finished.elapsed() >= RESULT_TIME)
+    }
+}
+
+fn update(
+    source: &dyn ReleaseSource,
+    channel: Channel,
+    sender: &Sender<UpdateState>,
+    confirmed: &Receiver<()>,
+) -> Result<UpdateState, UpdateErrorKind> {
+    let mut newer: Vec<_> = source
+        .releases()?
+        .into_iter()
+        .filter(|release| {
+            channel.includes(&release.version)
+                && is_newer(env!("CARGO_PKG_VERSION"), &release.version)
+        })
+        .collect();
+    newer.sort_by(|a, b| {
+        if is_newer(&a.version, &b.version) {
+            Ordering::Greater
+        } else if is_newer(&b.version, &a.version) {
+            Ordering::Less
+        } else {
+            Ordering::Equal
+        }
+    });
+    let latest = match newer.first() {
+        Some(latest) => latest,
+        None => return Ok(UpdateState::UpToDate),
+    };
+    let target = self_update::get_target();
+    let asset = latest
+        .asset_for(target)
+        .ok_or_else(|| UpdateErrorKind::NoBuild(target.into()))?;
+    // Nothing is downloaded before the user agrees, not even the signature.
+    if latest.signature_for(asset).is_none() {
+        return Err(UpdateErrorKind::Unsigned);
+    }
+
+    let changes = newer
+        .iter()
+        .map(|release| Changes {
+            version: release.version.clone(),
+            notes: release.notes.clone(),
+        })
+        .collect();
+    let _ = sender.send(UpdateState::Available(changes));
+    // Fails once the launcher drops the updater, declining the update.
+    confirmed.recv().map_err(|_| UpdateErrorKind::Interrupted)?;
+
+    let install = |e: std::io::Error| UpdateErrorKind::Install(e.to_string());
+    let exe = env::current_exe().map_err(install)?;
+    let download = exe.with_extension("download");
+    let result = signature::public_key()
+        .and_then(|public_key| {
+            let signature = fetch_signature(source, latest, asset)?;
+            download_verified(
+                source,
+                latest,
+                asset,
+                &signature,
+                &public_key,
+                &download,
+                sender,
+            )
+        })
+        .and_then(|_| {
+            let _ = sender.send(UpdateState::Installing);
+            replace(&download, &exe).map_err(install)
+        })
+        .and_then(|_| rollback::mark_pending(&exe, &latest.version).map_err(install));
+    let _ = fs::remove_file(&download);
+    result.map(|_| UpdateState::Updated(latest.version.clone()))
+}
+
+/// Whether `version` is later than `than`; versions that don't parse never are.
+fn is_newer(than: &str, version: &str) -> bool {
+    self_update::version::bump_is_greater(than, version).unwrap_or(false)
+}
+
+/// Reads the detached signature of `asset`, a file of `release`.
+fn fetch_signature(
+    source: &dyn ReleaseSource,
+    release: &Release,
+    asset: &source::Asset,
+) -> Result<Vec<u8>, UpdateErrorKind> {
+    let signature = release
+        .signature_for(asset)
+        .ok_or(UpdateErrorKind::Unsigned)?;
+    let (reader, _) = source.open(signature)?;
+    let mut signature = Vec::with_capacity(SIGNATURE_LEN);
+    // One byte more tells a longer file from a signature.
+    reader
+        .take(SIGNATURE_LEN as u64 + 1)
+        .read_to_end(&mut signature)
+        .map_err(|e| UpdateErrorKind::Network(e.to_string()))?;
+    if signature.len() != SIGNATURE_LEN {
+        return Err(UpdateErrorKind::BadSignature);
+    }
+    Ok(signature)
+}
+
+/// Downloads `asset` to `path` and checks it against `signature`, which has to be made for the
+/// version of `release`. The file is left for the caller to remove either way.
+fn download_verified(
+    source: &dyn ReleaseSource,
+    release: &Release,
+    asset: &source::Asset,
+    signature: &[u8],
+    public_key: &[u8],
+    path: &Path,
+    sender: &Sender<UpdateState>,
+) -> Result<(), UpdateErrorKind> {
+    download_to(source, asset, path, sender)?;
+    let file = fs::read(path).map_err(|e| UpdateErrorKind::Install(e.to_string()))?;
+    signature::verify(public_key, &release.version, &file, signature)
+}
+
+/// Downloads `asset` to `path`, reporting progress to `sender`.
+fn download_to(
+    source: &dyn ReleaseSource,
+    asset: &source::Asset,
+    path: &Path,
+    sender: &Sender<UpdateState>,
+) -> Result<(), UpdateErrorKind> {
+    let install = |e: std::io::Error| UpdateErrorKind::Install(e.to_string());
+    let (mut reader, total) = source.open(asset)?;
+    let mut file = fs::File::create(path).map_err(install)?;
+    let mut buffer = [0; 0x4000];
+    let mut done = 0;
+    let _ = sender.send(UpdateState::Downloading { done, total });
+    loop {
+        let read = reader
+            .read(&mut buffer)
+            .map_err(|e| UpdateErrorKind::Network(e.to_string()))?;
+        if read == 0 {
+            break;
+        }
+        file.write_all(&buffer[..read]).map_err(install)?;
+        done += read as u64;
+        let _ = sender.send(UpdateState::Downloading { done, total });
+    }
+    file.sync_all().map_err(install)
+}
+
+/// Puts the downloaded executable in place of the running one. Windows can't overwrite a
+/// running executable but can rename it, so the old one is moved aside first, where it stays
+/// to roll back to.
+fn replace(download: &Path, exe: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(download, fs::Permissions::from_mode(0o755))?;
+    }
+    let old = rollback::previous_path(exe);
+    let _ = fs::remove_file(&old);
+    self_update::Move::from_source(download)
+        .replace_using_temp(&old)
+        .to_dest(exe)
+        .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::Read,
+        path::Path,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc, Arc,
+        },
+    };
+
+    use tempfile::TempDir;
+
+    use super::{
+        download_verified, fetch_signature,
+        source::{self, Asset, Release, ReleaseSource, MANIFEST_FILE},
+        update, UpdateErrorKind, UpdateState,
+    };
+    use crate::config::{Channel, UpdateSource};
+
+    /// Public half of the key the fixtures are signed with.
+    const FIXTURE_KEY: [u8; 32] = [
+        0x58, 0x8d, 0xdf, 0x3a, 0x70, 0x2e, 0xc3, 0x3e, 0xf1, 0x0d, 0x72, 0xa7, 0xaf, 0xcc, 0xc7,
+        0x90, 0x0f, 0x6b, 0xa0, 0xbd, 0xfb, 0x70, 0x5c, 0xf4, 0x73, 0x95, 0x4e, 0x22, 0xca, 0x3e,
+        0xea, 0x18,
+    ];
+
+    fn fixtures() -> Box<dyn ReleaseSource> {
+        source::from_config(&UpdateSource::Directory {
+            path: Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/update"),
+        })
+    }
+
+    /// Counts the files opened through it.
+    struct Counting {
+        inner: Box<dyn ReleaseSource>,
+        opened: Arc<AtomicUsize>,
+    }
+
+    impl ReleaseSource for Counting {
+        fn releases(&self) -> Result<Vec<Release>, UpdateErrorKind> {
+            self.inner.releases()
+        }
+
+        fn open(&self, asset: &Asset) -> Result<(Box<dyn Read>, Option<u64>), UpdateErrorKind> {
+            self.opened.fetch_add(1, Ordering::SeqCst);
+            self.inner.open(asset)
+        }
+    }
+
+    fn release(source: &dyn ReleaseSource, version: &str) -> Release {
+        source
+            .releases()
+            .unwrap()
+            .into_iter()
+            .find(|release| release.version == version)
+            .unwrap()
+    }
+
+    /// Fetches the signature of the build of `version` and downloads it to a temporary folder,
+    /// checking it with `key`.
+    fn install(version: &str, key: &[u8]) -> Result<(), UpdateErrorKind> {
+        install_as(version, version, key)
+    }
+
+    /// Like `install`, with the build of `version` listed as the release `offered`.
+    fn install_as(version: &str, offered: &str, key: &[u8]) -> Result<(), UpdateErrorKind> {
+        let source = fixtures();
+        let release = Release {
+            version: offered.into(),
+            ..release(&*source, version)
+        };
+        let asset = release.asset_for(&format!("SCBank-{}", version)).unwrap();
+        let signature = fetch_signature(&*source, &release, asset)?;
+        let dir = TempDir::new().unwrap();
+        let (sender, _receiver) = mpsc::channel();
+        let path = dir.path().join("download");
+        download_verified(&*source, &release, asset, &signature, key, &path, &sender)
+    }
+
+    #[test]
+    fn installs_signed_builds() {
+        assert_eq!(install("0.2.0", &FIXTURE_KEY), Ok(()));
+    }
+
+    #[test]
+    fn refuses_changed_builds() {
+        assert_eq!(
+            install("0.3.0", &FIXTURE_KEY),
+            Err(UpdateErrorKind::BadSignature)
+        );
+    }
+
+    #[test]
+    fn refuses_builds_offered_as_another_version() {
+        // An old build and its signature, listed as a newer release to downgrade SCBank.
+        assert_eq!(
+            install_as("0.2.0", "0.9.0", &FIXTURE_KEY),
+            Err(UpdateErrorKind::BadSignature)
+        );
+    }
+
+    #[test]
+    fn refuses_unsigned_builds() {
+        assert_eq!(
+            install("0.4.0", &FIXTURE_KEY),
+            Err(UpdateErrorKind::Unsigned)
+        );
+    }
+
+    #[test]
+    fn refuses_builds_signed_with_another_key() {
+        let mut key = FIXTURE_KEY;
+        key[0] ^= 1;
+        assert_eq!(install("0.2.0", &key), Err(UpdateErrorKind::BadSignature));
+    }
+
+    #[test]
+    fn downloads_nothing_until_confirmed() {
+        let dir = TempDir::new().unwrap();
+        let exe = format!("SCBank-9.0.0-{}.exe", self_update::get_target());
+        let manifest = format!(
+            r#"{{ "releases": [{{ "version": "9.0.0", "assets": [{:?}, "{}.sig"] }}] }}"#,
+            exe, exe
+        );
+        fs::write(dir.path().join(MANIFEST_FILE), manifest).unwrap();
+        let opened = Arc::new(AtomicUsize::new(0));
+        let source = Counting {
+            inner: source::from_config(&UpdateSource::Directory {
+                path: dir.path().to_path_buf(),
+            }),
+            opened: opened.clone(),
+        };
+        let (sender, receiver) = mpsc::channel();
+        // The launcher went away without installing.
+        let (_, confirmed) = mpsc::channel();
+
+        let result = update(&source, Channel::Stable, &sender, &confirmed);
+        assert!(matches!(result, Err(UpdateErrorKind::Interrupted)));
+        assert!(matches!(receiver.try_recv(), Ok(UpdateState::Available(_))));
+        assert_eq!(opened.load(Ordering::SeqCst), 0);
+    }
+}
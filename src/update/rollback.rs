@@ -0,0 +1,177 @@
+//! Going back to the previous version after an update that doesn't work.
+//!
+//! Installing an update leaves the replaced executable next to the new one, and a marker
+//! telling the new version is on trial. The trial ends the first time it attaches to
+//! StarCraft. A version that is still on trial when it starts again never attached last time,
+//! so the launcher offers to roll back.
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Extension of the executable an update replaced.
+pub const PREVIOUS_EXTENSION: &str = "old";
+/// Extension of the marker of an update on trial.
+const PENDING_EXTENSION: &str = "pending";
+/// Written to the marker by the first start of the new version.
+const STARTED: &str = "started";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Startup {
+    Normal,
+    /// First start after an update.
+    Trial,
+    /// The update was started before and never attached.
+    Suspect,
+}
+
+/// The executable an update would replace, or replaced.
+pub fn previous_path(exe: &Path) -> PathBuf {
+    exe.with_extension(PREVIOUS_EXTENSION)
+}
+
+fn pending_path(exe: &Path) -> PathBuf {
+    exe.with_extension(PENDING_EXTENSION)
+}
+
+/// Puts the update to `version` on trial.
+pub fn mark_pending(exe: &Path, version: &str) -> io::Result<()> {
+    fs::write(pending_path(exe), version)
+}
+
+/// Looks at the trial of the last update, if any, and counts this start.
+pub fn on_startup() -> Startup {
+    match env::current_exe() {
+        Ok(exe) => count_start(&exe, env!("CARGO_PKG_VERSION")),
+        Err(_) => Startup::Normal,
+    }
+}
+
+/// `on_startup` for `exe` at `version`.
+fn count_start(exe: &Path, version: &str) -> Startup {
+    let pending = pending_path(exe);
+    let marker = match fs::read_to_string(&pending) {
+        Ok(marker) => marker,
+        Err(_) => return Startup::Normal,
+    };
+    let mut lines = marker.lines();
+    // A marker of another version is stale, as after rolling back by hand.
+    if lines.next() != Some(version) {
+        let _ = fs::remove_file(&pending);
+        return Startup::Normal;
+    }
+    if lines.next() == Some(STARTED) {
+        return Startup::Suspect;
+    }
+    let started = format!("{}\n{}", version, STARTED);
+    match fs::write(&pending, started) {
+        Ok(()) => Startup::Trial,
+        Err(_) => Startup::Normal,
+    }
+}
+
+/// Ends the trial of this version; it attached.
+pub fn confirm() {
+    if let Ok(exe) = env::current_exe() {
+        end_trial(&exe);
+    }
+}
+
+fn end_trial(exe: &Path) {
+    let _ = fs::remove_file(pending_path(exe));
+}
+
+/// Whether there is a previous version to go back to.
+pub fn can_roll_back() -> bool {
+    env::current_exe().is_ok_and(|exe| previous_path(&exe).is_file())
+}
+
+/// Swaps the running executable with the previous one, which runs from the next start. Rolling
+/// back again goes forward to the update.
+pub fn roll_back() -> io::Result<()> {
+    swap(&env::current_exe()?)
+}
+
+/// `roll_back` for `exe`.
+fn swap(exe: &Path) -> io::Result<()> {
+    let previous = previous_path(exe);
+    if !previous.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no previous version to roll back to",
+        ));
+    }
+    // Windows can rename a running executable, but not overwrite it.
+    let swapped = exe.with_extension("swap");
+    fs::rename(exe, &swapped)?;
+    if let Err(e) = fs::rename(&previous, exe) {
+        fs::rename(&swapped, exe)?;
+        return Err(e);
+    }
+    fs::rename(&swapped, &previous)?;
+    end_trial(exe);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use tempfile::TempDir;
+
+    use super::{count_start, end_trial, mark_pending, pending_path, previous_path, swap, Startup};
+
+    /// A folder with `SCBank.exe` updated to 0.2.0 from the build in `SCBank.old`.
+    fn updated() -> (TempDir, PathBuf) {
+        let dir = TempDir::new().unwrap();
+        let exe = dir.path().join("SCBank.exe");
+        fs::write(&exe, "0.2.0").unwrap();
+        fs::write(previous_path(&exe), "0.1.1").unwrap();
+        mark_pending(&exe, "0.2.0").unwrap();
+        (dir, exe)
+    }
+
+    #[test]
+    fn suspects_updates_that_never_attached() {
+        let (_dir, exe) = updated();
+        assert_eq!(count_start(&exe, "0.2.0"), Startup::Trial);
+        assert_eq!(count_start(&exe, "0.2.0"), Startup::Suspect);
+        assert_eq!(count_start(&exe, "0.2.0"), Startup::Suspect);
+
+        // Attaching confirms the update.
+        end_trial(&exe);
+        assert!(!pending_path(&exe).exists());
+        assert_eq!(count_start(&exe, "0.2.0"), Startup::Normal);
+    }
+
+    #[test]
+    fn drops_markers_of_other_versions() {
+        let (_dir, exe) = updated();
+        assert_eq!(count_start(&exe, "0.1.1"), Startup::Normal);
+        assert!(!pending_path(&exe).exists());
+        assert_eq!(count_start(&exe, "0.2.0"), Startup::Normal);
+    }
+
+    #[test]
+    fn rolls_back_and_forward() {
+        let (_dir, exe) = updated();
+        count_start(&exe, "0.2.0");
+        swap(&exe).unwrap();
+        assert_eq!(fs::read_to_string(&exe).unwrap(), "0.1.1");
+        assert_eq!(fs::read_to_string(previous_path(&exe)).unwrap(), "0.2.0");
+        assert!(!pending_path(&exe).exists());
+
+        swap(&exe).unwrap();
+        assert_eq!(fs::read_to_string(&exe).unwrap(), "0.2.0");
+        assert_eq!(fs::read_to_string(previous_path(&exe)).unwrap(), "0.1.1");
+    }
+
+    #[test]
+    fn needs_a_previous_version() {
+        let (_dir, exe) = updated();
+        fs::remove_file(previous_path(&exe)).unwrap();
+        assert!(swap(&exe).is_err());
+        assert_eq!(fs::read_to_string(&exe).unwrap(), "0.2.0");
+    }
+}
@@ -0,0 +1,64 @@
+//! Release files are signed with Ed25519. Each file comes with a detached signature, the 64
+//! bytes of it in a file of the same name plus `.sig`, and is only installed if the signature
+//! matches the public key this build was made with.
+//!
+//! The signature covers the version of the release, a NUL byte and then the file, so a file
+//! signed for an old release can't be offered again as a newer one to downgrade SCBank.
+//!
+//! The key is given in hex by `SCBANK_UPDATE_PUBLIC_KEY` when building. Release builds fail
+//! without it; debug builds without one check for updates but can't install them.
+
+use ring::signature::{self, ED25519, ED25519_PUBLIC_KEY_LEN};
+
+use super::UpdateErrorKind;
+
+pub const SIGNATURE_EXTENSION: &str = "sig";
+/// Length of an Ed25519 signature.
+pub const SIGNATURE_LEN: usize = 64;
+#[cfg(not(debug_assertions))]
+const PUBLIC_KEY: Option<&str> = Some(env!(
+    "SCBANK_UPDATE_PUBLIC_KEY",
+    "Release builds need the hex public key updates are signed with, see the README"
+));
+#[cfg(debug_assertions)]
+const PUBLIC_KEY: Option<&str> = option_env!("SCBANK_UPDATE_PUBLIC_KEY");
+
+/// The key releases are signed for, as embedded in this build.
+pub fn public_key() -> Result<[u8; ED25519_PUBLIC_KEY_LEN], UpdateErrorKind> {
+    PUBLIC_KEY
+        .and_then(parse_hex_key)
+        .ok_or(UpdateErrorKind::NoPublicKey)
+}
+
+/// Checks that `signature` was made for `file` as the release `version` with the private half
+/// of `public_key`.
+pub fn verify(
+    public_key: &[u8],
+    version: &str,
+    file: &[u8],
+    signature: &[u8],
+) -> Result<(), UpdateErrorKind> {
+    let mut signed = Vec::with_capacity(version.len() + 1 + file.len());
+    signed.extend_from_slice(version.as_bytes());
+    signed.push(0);
+    signed.extend_from_slice(file);
+    signature::verify(
+        &ED25519,
+        untrusted::Input::from(public_key),
+        untrusted::Input::from(&signed),
+        untrusted::Input::from(signature),
+    )
+    .map_err(|_| UpdateErrorKind::BadSignature)
+}
+
+fn parse_hex_key(hex: &str) -> Option<[u8; ED25519_PUBLIC_KEY_LEN]> {
+    let hex = hex.trim();
+    if hex.len() != ED25519_PUBLIC_KEY_LEN * 2 || !hex.is_ascii() {
+        return None;
+    }
+    let mut key = [0; ED25519_PUBLIC_KEY_LEN];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(key)
+}
@@ -0,0 +1,443 @@
+//! Where releases come from. Besides GitHub, a folder or a static HTTP server can serve them
+//! with a manifest, `releases.json`, listing the releases and naming their files:
+//!
+//! ```json
+//! {
+//!   "releases": [
+//!     {
+//!       "version": "0.2.0",
+//!       "assets": [
+//!         "SCBank-0.2.0-x86_64-pc-windows-msvc.exe",
+//!         "SCBank-0.2.0-x86_64-pc-windows-msvc.exe.sig"
+//!       ],
+//!       "notes": {
+//!         "en-US": "Saves load faster.",
+//!         "ko-KR": "세이브를 더 빨리 불러옵니다."
+//!       }
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Files are looked up next to the manifest. Every file needs its signature, see `signature`.
+//! The optional notes are shown before installing, either one text or one per locale.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use reqwest::header::{ACCEPT, USER_AGENT};
+use serde::Deserialize;
+
+use super::{signature::SIGNATURE_EXTENSION, UpdateErrorKind};
+use crate::config::UpdateSource;
+
+pub const MANIFEST_FILE: &str = "releases.json";
+const REPO_OWNER: &str = "armoha";
+const REPO_NAME: &str = "SCBank";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Release {
+    pub version: String,
+    pub assets: Vec<Asset>,
+    /// What changed since the previous release.
+    pub notes: Notes,
+}
+
+impl Release {
+    /// The file built for `target`, as named by `self_update::get_target`.
+    pub fn asset_for(&self, target: &str) -> Option<&Asset> {
+        self.assets
+            .iter()
+            .find(|asset| asset.name.contains(target) && !asset.is_signature())
+    }
+
+    /// The detached signature of `asset`.
+    pub fn signature_for(&self, asset: &Asset) -> Option<&Asset> {
+        let name = format!("{}.{}", asset.name, SIGNATURE_EXTENSION);
+        self.assets.iter().find(|signature| signature.name == name)
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum Notes {
+    Text(String),
+    /// Texts by locale, such as `ko-KR`.
+    Localized(BTreeMap<String, String>),
+}
+
+impl Default for Notes {
+    fn default() -> Self {
+        Notes::Text(String::new())
+    }
+}
+
+impl Notes {
+    /// The text for `locale`, else the English one, else any.
+    pub fn get(&self, locale: &str) -> &str {
+        match self {
+            Notes::Text(text) => text,
+            Notes::Localized(texts) => texts
+                .get(locale)
+                .or_else(|| texts.get("en-US"))
+                .or_else(|| texts.values().next())
+                .map_or("", String::as_str),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Asset {
+    pub name: String,
+    /// URL or path the source downloads the file from.
+    pub location: String,
+}
+
+impl Asset {
+    pub fn is_signature(&self) -> bool {
+        self.name.ends_with(&format!(".{}", SIGNATURE_EXTENSION))
+    }
+}
+
+pub trait ReleaseSource: Send {
+    /// Lists the releases, in any order.
+    fn releases(&self) -> Result<Vec<Release>, UpdateErrorKind>;
+
+    /// Opens a file of a release for reading, with its size if known.
+    fn open(&self, asset: &Asset) -> Result<(Box<dyn Read>, Option<u64>), UpdateErrorKind>;
+}
+
+/// The source configured in `scbank.toml`.
+pub fn from_config(source: &UpdateSource) -> Box<dyn ReleaseSource> {
+    match source {
+        UpdateSource::GitHub => Box::new(GitHub),
+        UpdateSource::Directory { path } => Box::new(Directory { path: path.clone() }),
+        UpdateSource::Mirror { url } => Box::new(Mirror {
+            // Files are named relative to the folder of the manifest.
+            base: if url.ends_with('/') {
+                url.clone()
+            } else {
+                format!("{}/", url)
+            },
+        }),
+    }
+}
+
+/// The releases of SCBank on GitHub.
+pub struct GitHub;
+
+impl ReleaseSource for GitHub {
+    fn releases(&self) -> Result<Vec<Release>, UpdateErrorKind> {
+        let network = |e: self_update::errors::Error| UpdateErrorKind::Network(e.to_string());
+        let releases = self_update::backends::github::ReleaseList::configure()
+            .repo_owner(REPO_OWNER)
+            .repo_name(REPO_NAME)
+            .build()
+            .map_err(network)?
+            .fetch()
+            .map_err(network)?;
+        Ok(releases
+            .into_iter()
+            .map(|release| Release {
+                version: release.version,
+                assets: release
+                    .assets
+                    .into_iter()
+                    .map(|asset| Asset {
+                        name: asset.name,
+                        location: asset.download_url,
+                    })
+                    .collect(),
+                notes: Notes::Text(release.body.unwrap_or_default()),
+            })
+            .collect())
+    }
+
+    fn open(&self, asset: &Asset) -> Result<(Box<dyn Read>, Option<u64>), UpdateErrorKind> {
+        get(&asset.location)
+    }
+}
+
+/// A folder holding a manifest and the files it names, such as a network share.
+pub struct Directory {
+    path: PathBuf,
+}
+
+impl ReleaseSource for Directory {
+    fn releases(&self) -> Result<Vec<Release>, UpdateErrorKind> {
+        let manifest = fs::read(self.path.join(MANIFEST_FILE))
+            .map_err(|e| UpdateErrorKind::Network(e.to_string()))?;
+        parse_manifest(&manifest, |name| self.path.join(name).display().to_string())
+    }
+
+    fn open(&self, asset: &Asset) -> Result<(Box<dyn Read>, Option<u64>), UpdateErrorKind> {
+        let file = fs::File::open(Path::new(&asset.location))
+            .map_err(|e| UpdateErrorKind::Network(e.to_string()))?;
+        let len = file.metadata().ok().map(|metadata| metadata.len());
+        Ok((Box::new(file), len))
+    }
+}
+
+/// A static HTTP server holding a manifest and the files it names.
+pub struct Mirror {
+    /// URL of the folder with the manifest, ending with a slash.
+    base: String,
+}
+
+impl ReleaseSource for Mirror {
+    fn releases(&self) -> Result<Vec<Release>, UpdateErrorKind> {
+        let (mut response, _) = get(&format!("{}{}", self.base, MANIFEST_FILE))?;
+        let mut manifest = Vec::new();
+        response
+            .read_to_end(&mut manifest)
+            .map_err(|e| UpdateErrorKind::Network(e.to_string()))?;
+        parse_manifest(&manifest, |name| self.locate(name))
+    }
+
+    fn open(&self, asset: &Asset) -> Result<(Box<dyn Read>, Option<u64>), UpdateErrorKind> {
+        get(&asset.location)
+    }
+}
+
+impl Mirror {
+    /// The URL of the file `name`, which may hold spaces or `#`.
+    fn locate(&self, name: &str) -> String {
+        format!("{}{}", self.base, urlencoding::encode(name))
+    }
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    releases: Vec<ManifestRelease>,
+}
+
+#[derive(Deserialize)]
+struct ManifestRelease {
+    version: String,
+    #[serde(default)]
+    assets: Vec<String>,
+    #[serde(default)]
+    notes: Notes,
+}
+
+/// Reads a manifest; `locate` turns a file name into where to get it.
+fn parse_manifest(
+    manifest: &[u8],
+    locate: impl Fn(&str) -> String,
+) -> Result<Vec<Release>, UpdateErrorKind> {
+    let manifest: Manifest = serde_json::from_slice(manifest)
+        .map_err(|e| UpdateErrorKind::BadManifest(e.to_string()))?;
+    let mut releases = Vec::with_capacity(manifest.releases.len());
+    for release in manifest.releases {
+        let mut assets = Vec::with_capacity(release.assets.len());
+        for name in release.assets {
+            // Only files next to the manifest.
+            if !is_file_name(&name) {
+                return Err(UpdateErrorKind::BadManifest(name));
+            }
+            assets.push(Asset {
+                location: locate(&name),
+                name,
+            });
+        }
+        releases.push(Release {
+            version: release.version,
+            assets,
+            notes: release.notes,
+        });
+    }
+    Ok(releases)
+}
+
+/// Whether `name` names a file in the folder of the manifest, with no path to elsewhere.
+fn is_file_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && !name.contains("..") && !name.contains(['/', '\\', ':'])
+}
+
+fn get(url: &str) -> Result<(Box<dyn Read>, Option<u64>), UpdateErrorKind> {
+    let response = reqwest::blocking::Client::new()
+        .get(url)
+        .header(ACCEPT, "application/octet-stream")
+        .header(USER_AGENT, REPO_NAME)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| UpdateErrorKind::Network(e.to_string()))?;
+    let len = response.content_length();
+    Ok((Box::new(response), len))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use tempfile::TempDir;
+
+    use super::{Asset, Directory, Mirror, Notes, ReleaseSource, MANIFEST_FILE};
+    use crate::update::UpdateErrorKind;
+
+    fn folder(manifest: &str) -> (TempDir, Directory) {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join(MANIFEST_FILE), manifest).unwrap();
+        let source = Directory {
+            path: dir.path().to_path_buf(),
+        };
+        (dir, source)
+    }
+
+    #[test]
+    fn lists_the_releases_of_a_folder() {
+        let (dir, source) = folder(
+            r#"{
+                "releases": [
+                    {
+                        "version": "0.2.0",
+                        "assets": ["SCBank.exe", "SCBank.exe.sig"],
+                        "notes": { "en-US": "Faster", "ko-KR": "빨라짐" }
+                    },
+                    { "version": "0.1.1" }
+                ]
+            }"#,
+        );
+        let releases = source.releases().unwrap();
+        assert_eq!(releases.len(), 2);
+
+        let release = &releases[0];
+        assert_eq!(release.version, "0.2.0");
+        let exe = release.asset_for("SCBank").unwrap();
+        assert_eq!(
+            exe.location,
+            dir.path().join("SCBank.exe").display().to_string()
+        );
+        assert_eq!(release.signature_for(exe).unwrap().name, "SCBank.exe.sig");
+        assert_eq!(release.notes.get("ko-KR"), "빨라짐");
+        assert_eq!(release.notes.get("zh-CN"), "Faster");
+
+        assert!(releases[1].assets.is_empty());
+        assert_eq!(releases[1].notes, Notes::default());
+    }
+
+    #[test]
+    fn rejects_files_outside_the_folder() {
+        for name in &[
+            "",
+            ".",
+            "..",
+            "../SCBank.exe",
+            "bin/SCBank.exe",
+            r"bin\SCBank.exe",
+            "C:SCBank.exe",
+        ] {
+            let manifest = format!(
+                r#"{{ "releases": [{{ "version": "0.2.0", "assets": [{:?}] }}] }}"#,
+                name
+            );
+            let (_dir, source) = folder(&manifest);
+            assert_eq!(
+                source.releases(),
+                Err(UpdateErrorKind::BadManifest(name.to_string())),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_manifests() {
+        let (_dir, source) = folder(r#"{ "releases": [{ "assets": [] }] }"#);
+        assert!(matches!(
+            source.releases(),
+            Err(UpdateErrorKind::BadManifest(_))
+        ));
+    }
+
+    #[test]
+    fn encodes_mirror_urls() {
+        let mirror = Mirror {
+            base: "https://example.com/scbank/".into(),
+        };
+        assert_eq!(
+            mirror.locate("SCBank 0.2 #1.exe"),
+            "https://example.com/scbank/SCBank%200.2%20%231.exe"
+        );
+    }
+
+    const MIRROR_MANIFEST: &[u8] =
+        br#"{ "releases": [{ "version": "0.2.0", "assets": ["SCBank 0.2.exe", "short.exe"] }] }"#;
+
+    /// Serves `releases.json` and a few files over HTTP on a local port until the test ends,
+    /// returning a mirror of it.
+    fn server() -> Mirror {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}/scbank/", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                // Skip the headers.
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let path = request.split(' ').nth(1).unwrap_or_default();
+                // Status, body and the length announced for the body.
+                let (status, body, len): (_, &[u8], _) = match path {
+                    "/scbank/releases.json" => ("200 OK", MIRROR_MANIFEST, MIRROR_MANIFEST.len()),
+                    "/scbank/SCBank%200.2.exe" => ("200 OK", b"MZexe", 5),
+                    // The connection drops before the promised length.
+                    "/scbank/short.exe" => ("200 OK", b"MZ", 100),
+                    _ => ("404 Not Found", b"", 0),
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status, len
+                )
+                .unwrap();
+                stream.write_all(body).unwrap();
+            }
+        });
+        Mirror { base }
+    }
+
+    #[test]
+    fn downloads_from_a_mirror() {
+        let mirror = server();
+        let releases = mirror.releases().unwrap();
+        let asset = releases[0].asset_for("SCBank").unwrap();
+        assert_eq!(asset.location, format!("{}SCBank%200.2.exe", mirror.base));
+
+        let (mut file, len) = mirror.open(asset).unwrap();
+        let mut exe = Vec::new();
+        file.read_to_end(&mut exe).unwrap();
+        assert_eq!((exe.as_slice(), len), (&b"MZexe"[..], Some(5)));
+    }
+
+    #[test]
+    fn fails_on_broken_mirror_downloads() {
+        let mirror = server();
+        let missing = Asset {
+            name: "missing.exe".into(),
+            location: mirror.locate("missing.exe"),
+        };
+        assert!(matches!(
+            mirror.open(&missing),
+            Err(UpdateErrorKind::Network(_))
+        ));
+
+        let releases = mirror.releases().unwrap();
+        let short = releases[0].asset_for("short").unwrap();
+        let (mut file, _) = mirror.open(short).unwrap();
+        assert!(file.read_to_end(&mut Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,31 @@
+#!/bin/sh
+# Signs the update fixtures the way releases are signed, see "Updates" in the README.
+#
+# The key is derived from a fixed seed, so it is the same on every run and matches
+# `FIXTURE_KEY` in `src/update.rs`; it signs nothing but these fixtures. 0.3.0 is changed after
+# signing and 0.4.0 stays unsigned, as the tests expect.
+set -e
+cd "$(dirname "$0")"
+key=$(mktemp)
+signed=$(mktemp)
+trap 'rm -f "$key" "$signed"' EXIT
+
+# PKCS #8 header of an Ed25519 private key, then the seed.
+{
+    printf '302e020100300506032b657004220420'
+    printf 'SCBank update test key' | openssl dgst -sha256 -r | cut -c1-64
+} | xxd -r -p | openssl pkey -inform DER -out "$key"
+
+sign() {
+    { printf '%s\0' "$1"; cat "SCBank-$1.exe"; } > "$signed"
+    openssl pkeyutl -sign -rawin -inkey "$key" -in "$signed" -out "SCBank-$1.exe.sig"
+}
+
+printf 'SCBank 0.2.0 test build\n' > SCBank-0.2.0.exe
+sign 0.2.0
+printf 'SCBank 0.3.0 test build\n' > SCBank-0.3.0.exe
+sign 0.3.0
+printf 'SCBank 0.3.0 test build, patched\n' > SCBank-0.3.0.exe
+printf 'SCBank 0.4.0 test build\n' > SCBank-0.4.0.exe
+
+echo "Public key: $(openssl pkey -in "$key" -pubout -outform DER | tail -c 32 | xxd -p -c 32)"